@@ -1,4 +1,11 @@
+use std::env;
+use std::path::PathBuf;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/iam.proto")?;
+    // The descriptor set is served by the optional reflection service
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("iam_descriptor.bin"))
+        .compile_protos(&["proto/iam.proto"], &["proto"])?;
     Ok(())
-}
\ No newline at end of file
+}
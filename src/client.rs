@@ -57,6 +57,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let check_request = Request::new(CheckPermissionsRequest {
             user_id: user_id.clone(),
             required_permissions: vec!["READ".to_string(), "WRITE".to_string()],
+            mode: MatchMode::All as i32,
         });
 
         let response = client.check_permissions(check_request).await?;
@@ -72,6 +73,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let check_request = Request::new(CheckPermissionsRequest {
             user_id: user_id.clone(),
             required_permissions: vec!["EXECUTE".to_string()],
+            mode: MatchMode::All as i32,
         });
 
         let response = client.check_permissions(check_request).await?;
@@ -88,6 +90,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("\n=== Adding a new permission ===");
         let add_perm_request = Request::new(AddPermissionRequest {
             permission_name: "ADMIN".to_string(),
+            label: "Administrator".to_string(),
+            max_holders: None,
+            requested_value: None,
+            description: "Full administration access".to_string(),
         });
 
         let response = client.add_permission(add_perm_request).await?;
@@ -116,6 +122,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("User permissions updated successfully!");
             let updated_user = update_response.user.unwrap();
             println!("  New permissions: {:?}", updated_user.permissions);
+            println!("  Added: {:?}, removed: {:?}", update_response.added_permissions, update_response.removed_permissions);
         } else {
             println!("Failed to update permissions: {}", update_response.message);
         }
@@ -125,6 +132,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let check_request = Request::new(CheckPermissionsRequest {
             user_id: user_id.clone(),
             required_permissions: vec!["READ".to_string(), "EXECUTE".to_string(), "ADMIN".to_string()],
+            mode: MatchMode::All as i32,
         });
 
         let response = client.check_permissions(check_request).await?;
@@ -172,6 +180,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let check_request = Request::new(CheckPermissionsRequest {
             user_id: user_id.clone(),
             required_permissions: vec!["NONEXISTENT".to_string()],
+            mode: MatchMode::All as i32,
         });
 
         let response = client.check_permissions(check_request).await?;
@@ -0,0 +1,75 @@
+use std::fmt;
+use std::sync::PoisonError;
+
+use tonic::{Code, Status};
+
+// Failure of a manager operation. The variant decides the gRPC status code, the message is
+// what clients see in the `message` field or the status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IamError {
+    NotFound(String),
+    AlreadyExists(String),
+    InvalidArgument(String),
+    Unauthenticated(String),
+    ResourceExhausted(String),
+    FailedPrecondition(String),
+    // The manager cannot serve requests, e.g. its store failed to load
+    Unavailable(String),
+    // Every problem found while validating a set of entries (e.g. an import), reported together
+    Conflicts(Vec<String>),
+    Internal(String),
+}
+
+impl IamError {
+    pub fn code(&self) -> Code {
+        match self {
+            IamError::NotFound(_) => Code::NotFound,
+            IamError::AlreadyExists(_) => Code::AlreadyExists,
+            IamError::InvalidArgument(_) | IamError::Conflicts(_) => Code::InvalidArgument,
+            IamError::Unauthenticated(_) => Code::Unauthenticated,
+            IamError::ResourceExhausted(_) => Code::ResourceExhausted,
+            IamError::FailedPrecondition(_) => Code::FailedPrecondition,
+            IamError::Unavailable(_) => Code::Unavailable,
+            IamError::Internal(_) => Code::Internal,
+        }
+    }
+
+    // The individual problems behind the error, a single one unless it is Conflicts
+    pub fn details(&self) -> Vec<String> {
+        match self {
+            IamError::Conflicts(conflicts) => conflicts.clone(),
+            other => vec![other.to_string()],
+        }
+    }
+}
+
+impl fmt::Display for IamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IamError::NotFound(message)
+            | IamError::AlreadyExists(message)
+            | IamError::InvalidArgument(message)
+            | IamError::Unauthenticated(message)
+            | IamError::ResourceExhausted(message)
+            | IamError::FailedPrecondition(message)
+            | IamError::Unavailable(message)
+            | IamError::Internal(message) => f.write_str(message),
+            IamError::Conflicts(conflicts) => f.write_str(&conflicts.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for IamError {}
+
+// A poisoned lock means a thread panicked mid-mutation
+impl<T> From<PoisonError<T>> for IamError {
+    fn from(_: PoisonError<T>) -> Self {
+        IamError::Internal("Failed to acquire lock".to_string())
+    }
+}
+
+impl From<IamError> for Status {
+    fn from(error: IamError) -> Self {
+        Status::new(error.code(), error.to_string())
+    }
+}
@@ -1,171 +1,2754 @@
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use bcrypt::{hash, verify, DEFAULT_COST};
+use uuid::Uuid;
 use tonic::{Request, Response, Status};
 
 use crate::iam::iam_service_server::IamService;
 use crate::iam::*;
-use crate::models::{User, Permission};
-use crate::utils::{permission_names_to_bits, bits_to_permission_names, find_next_available_bit};
+use crate::error::IamError;
+use crate::store::{Store, StoreSnapshot, JsonFileStore};
+use crate::metrics::{CheckMetrics, MetricsSnapshot};
+use crate::models::{User, NewUser, Permission, PermissionRegistry, Role, PasswordPolicy, SortOrder, MatchMode, IntegrityViolation, IntegrityViolationKind, AuditAction, AuditEntry};
+use crate::utils::{normalize_permission_name, validate_permission_name, permission_names_to_bits, expand_permission_wildcards, bits_to_permission_names_with_order, compute_missing_bits, unix_timestamp, find_next_available_bit, permissions_complement, registry_mask, orphan_bits, bcrypt_cost, validate_bcrypt_cost, validate_email, split_bits, validate_permission_values, merge_registries, next_sequence};
 
-pub struct IamManager {
+// Page size used by ListUsers when the request leaves it at 0
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+// Users read per lock by StreamUsers
+pub const STREAM_CHUNK_SIZE: usize = 500;
+
+// Number of audit entries kept before the oldest are dropped
+pub const DEFAULT_AUDIT_CAPACITY: usize = 1000;
+
+// Per-user outcome of a batch operation: resulting permission names or the failure reason
+pub type BatchUserResult = (String, Result<Vec<String>, IamError>);
+
+// User with its permission names, as listed by ListUsers
+pub type UserWithPermissions = (User, Vec<String>);
+
+// Audit entry with the permission names before and after the change
+pub type RenderedAuditEntry = (AuditEntry, Vec<String>, Vec<String>);
+
+// Updated user with the permission names added and removed by the update
+pub type PermissionUpdate = (User, Vec<String>, Vec<String>);
+
+// Exported user with its permission names, the roles it holds in full and its audit entries
+pub type UserExport = (User, Vec<String>, Vec<String>, Vec<RenderedAuditEntry>);
+
+// Permission with its holder count and the names of the composites and roles referencing it
+pub type PermissionDescription = (Permission, usize, Vec<String>, Vec<String>);
+
+fn user_not_found() -> IamError {
+    IamError::NotFound("User not found".to_string())
+}
+
+fn permission_not_found() -> IamError {
+    IamError::NotFound("Permission not found".to_string())
+}
+
+fn match_mode(value: i32) -> Result<MatchMode, IamError> {
+    match crate::iam::MatchMode::try_from(value) {
+        Ok(crate::iam::MatchMode::All) => Ok(MatchMode::All),
+        Ok(crate::iam::MatchMode::Any) => Ok(MatchMode::Any),
+        Err(_) => Err(IamError::InvalidArgument(format!("Unknown match mode {}", value))),
+    }
+}
+
+fn check_permissions_message(has_permissions: bool, mode: MatchMode, missing_permissions: &[String]) -> String {
+    if has_permissions && mode == MatchMode::Any {
+        "User has at least one required permission".to_string()
+    } else if has_permissions {
+        "User has all required permissions".to_string()
+    } else {
+        format!("User is missing permissions: {}", missing_permissions.join(", "))
+    }
+}
+
+impl From<Permission> for crate::iam::Permission {
+    fn from(permission: Permission) -> Self {
+        Self {
+            name: permission.name,
+            value: permission.value,
+            label: permission.label,
+            max_holders: permission.max_holders,
+            description: permission.description,
+            created_at: permission.created_at,
+            composite: permission.composite,
+        }
+    }
+}
+
+impl From<crate::iam::Permission> for Permission {
+    fn from(permission: crate::iam::Permission) -> Self {
+        Self {
+            name: permission.name,
+            value: permission.value,
+            label: permission.label,
+            max_holders: permission.max_holders,
+            description: permission.description,
+            created_at: permission.created_at,
+            composite: permission.composite,
+            sequence: 0,
+        }
+    }
+}
+
+// The user with the given email, compared trimmed and case-insensitively. Should several users share
+// it, as stores written before emails were unique may, the one with the smallest id.
+fn user_by_email<'a>(users: &'a HashMap<String, User>, email: &str) -> Option<&'a User> {
+    let email_key = email.trim().to_lowercase();
+    users.values()
+        .filter(|u| u.email.trim().to_lowercase() == email_key)
+        .min_by(|a, b| a.id.cmp(&b.id))
+}
+
+
+// Users with an id after page_token, sorted by id. Only the page itself is sorted, so walking
+// every page stays linear in the number of users per page.
+fn users_page(
+    permissions: &HashMap<String, Permission>,
+    users: &HashMap<String, User>,
+    page_size: usize,
+    page_token: &str,
+    sort_order: SortOrder,
+    collapse_composites: bool,
+) -> Result<(Vec<UserWithPermissions>, String), IamError> {
+    let mut sorted: Vec<&User> = users.values()
+        .filter(|u| page_token.is_empty() || u.id.as_str() > page_token)
+        .collect();
+    let has_more = sorted.len() > page_size;
+    if has_more {
+        sorted.select_nth_unstable_by(page_size - 1, |a, b| a.id.cmp(&b.id));
+        sorted.truncate(page_size);
+    }
+    sorted.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let next_page_token = match sorted.last() {
+        Some(last) if has_more => last.id.clone(),
+        _ => String::new(),
+    };
+
+    let mut page = Vec::new();
+    let now = unix_timestamp();
+    for user in sorted {
+        let permission_names = bits_to_permission_names_with_order(permissions, user.effective_permissions(now), sort_order, collapse_composites)?;
+        page.push((user.clone(), permission_names));
+    }
+
+    Ok((page, next_page_token))
+}
+
+// Users sorted by id, as streamed by StreamUsers. Each chunk is read under a short lock when the
+// previous one is used up, so the registry is never copied or locked as a whole.
+pub struct UserChunks {
+    permissions: Arc<RwLock<HashMap<String, Permission>>>,
     users: Arc<RwLock<HashMap<String, User>>>,
+    sort_order: SortOrder,
+    collapse_composites: bool,
+    chunk_size: usize,
+    // Id after which the next chunk starts, None once the last chunk is read
+    next_token: Option<String>,
+    chunk: std::vec::IntoIter<UserWithPermissions>,
+}
+
+impl UserChunks {
+    fn read_chunk(&self, token: &str) -> Result<(Vec<UserWithPermissions>, String), IamError> {
+        let permissions = self.permissions.read()?;
+        let users = self.users.read()?;
+        users_page(&permissions, &users, self.chunk_size, token, self.sort_order, self.collapse_composites)
+    }
+}
+
+impl Iterator for UserChunks {
+    type Item = Result<UserWithPermissions, IamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(user) = self.chunk.next() {
+            return Some(Ok(user));
+        }
+        let token = self.next_token.take()?;
+        match self.read_chunk(&token) {
+            Ok((chunk, next_token)) => {
+                self.next_token = Some(next_token).filter(|token| !token.is_empty());
+                self.chunk = chunk.into_iter();
+                self.chunk.next().map(Ok)
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+// StreamUsers messages, password hashes are never sent
+pub struct UserMessages(UserChunks);
+
+impl Iterator for UserMessages {
+    type Item = Result<crate::iam::User, Status>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let message = match self.0.next()? {
+            Ok((user, permission_names)) => Ok(crate::iam::User {
+                id: user.id,
+                name: user.name,
+                email: user.email,
+                permissions: permission_names,
+            }),
+            Err(e) => Err(e.into()),
+        };
+        Some(message)
+    }
+}
+
+// Locks are always taken in field order (permissions, roles, users, then the rest) so that
+// concurrent operations holding several of them cannot deadlock
+pub struct IamManager {
     permissions: Arc<RwLock<HashMap<String, Permission>>>,
+    roles: Arc<RwLock<HashMap<String, Role>>>,
+    users: Arc<RwLock<HashMap<String, User>>>,
+    // Bits skipped by automatic allocation until explicitly claimed
+    reserved_bits: Arc<RwLock<u64>>,
+    // Append-only ring buffer of user mutations
+    audit_log: Arc<RwLock<VecDeque<AuditEntry>>>,
+    // API key -> user id, kept in memory only
+    api_keys: Arc<RwLock<HashMap<String, String>>>,
+    metrics: CheckMetrics,
+    audit_capacity: usize,
+    require_nonempty_permissions: bool,
+    additive_only: bool,
+    sort_order: SortOrder,
+    collapse_composites: bool,
+    // Report failures as gRPC status codes rather than success: false responses
+    status_codes: bool,
+    // bcrypt cost factor used when hashing new passwords
+    password_cost: u32,
+    // Verified against when the email is unknown so both failures take the same time. Hashed at
+    // password_cost on first use.
+    dummy_password_hash: OnceLock<String>,
+    password_policy: PasswordPolicy,
+    max_permissions: Option<usize>,
+    id_prefix: Option<String>,
+    templates: HashMap<String, Vec<Permission>>,
+    // Locked for the whole of each mutation (before any other lock), so mutations are saved
+    // in order and a failed save can be rolled back
+    store: Option<Mutex<Persistence>>,
+    // Why the manager refuses writes and permission checks, set when its state could not be loaded
+    unavailable: Option<String>,
+}
+
+// Store backend and the last snapshot it accepted
+struct Persistence {
+    store: Box<dyn Store>,
+    saved: Option<StoreSnapshot>,
+}
+
+impl Default for IamManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl IamManager {
     pub fn new() -> Self {
-        let mut permissions = HashMap::new();
+        // Default permissions (using 64-bit values): READ=1, WRITE=2, EXECUTE=4, DELETE=8
+        Self::with_permissions(vec![
+            ("READ".to_string(), "Read access".to_string()),
+            ("WRITE".to_string(), "Write access".to_string()),
+            ("EXECUTE".to_string(), "Execute access".to_string()),
+            ("DELETE".to_string(), "Delete access".to_string()),
+        ])
+    }
 
-        // Default permissions (using 64-bit values)
-        permissions.insert("READ".to_string(), Permission::new("READ".to_string(), 1 << 0));
-        permissions.insert("WRITE".to_string(), Permission::new("WRITE".to_string(), 1 << 1));
-        permissions.insert("EXECUTE".to_string(), Permission::new("EXECUTE".to_string(), 1 << 2));
-        permissions.insert("DELETE".to_string(), Permission::new("DELETE".to_string(), 1 << 3));
+    // Starts with the given (name, description) permissions, assigned bits in order.
+    // Duplicate names are skipped and seeding stops once the 64 bits are used.
+    pub fn with_permissions(seeds: Vec<(String, String)>) -> Self {
+        let mut permissions: HashMap<String, Permission> = HashMap::new();
+
+        for (name, description) in seeds {
+            let name = normalize_permission_name(&name);
+            if permissions.contains_key(&name) {
+                continue;
+            }
+            let used_values = permissions.values().filter(|p| !p.composite).map(|p| p.value).collect::<Vec<_>>();
+            let Ok(value) = find_next_available_bit(&used_values) else {
+                break;
+            };
+            let permission = Permission {
+                sequence: next_sequence(&permissions),
+                ..Permission::new_with_description(name.clone(), value, description)
+            };
+            permissions.insert(name, permission);
+        }
 
         Self {
-            users: Arc::new(RwLock::new(HashMap::new())),
             permissions: Arc::new(RwLock::new(permissions)),
+            roles: Arc::new(RwLock::new(HashMap::new())),
+            users: Arc::new(RwLock::new(HashMap::new())),
+            reserved_bits: Arc::new(RwLock::new(0)),
+            audit_log: Arc::new(RwLock::new(VecDeque::new())),
+            api_keys: Arc::new(RwLock::new(HashMap::new())),
+            metrics: CheckMetrics::default(),
+            audit_capacity: DEFAULT_AUDIT_CAPACITY,
+            require_nonempty_permissions: false,
+            additive_only: false,
+            sort_order: SortOrder::default(),
+            collapse_composites: false,
+            status_codes: false,
+            password_cost: DEFAULT_COST,
+            dummy_password_hash: OnceLock::new(),
+            password_policy: PasswordPolicy::default(),
+            max_permissions: None,
+            id_prefix: None,
+            templates: HashMap::new(),
+            store: None,
+            unavailable: None,
         }
     }
 
-    pub fn create_user(&self, name: String, email: String, password: String, permission_names: Vec<String>) -> Result<User, String> {
-        let permissions = self.permissions.read().map_err(|_| "Failed to acquire read lock")?;
-        let permissions_bits = permission_names_to_bits(&permissions, &permission_names)?;
-        drop(permissions);
-        let user = User::new(name, email, password, permissions_bits)?;
+    // Stands in for a manager whose store failed to load. Writes and permission checks fail with
+    // UNAVAILABLE rather than acting on an empty registry, and the health service reports NOT_SERVING.
+    pub fn unavailable(reason: impl Into<String>) -> Self {
+        Self { unavailable: Some(reason.into()), ..Self::new() }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.unavailable.is_none()
+    }
+
+    fn ensure_available(&self) -> Result<(), IamError> {
+        match &self.unavailable {
+            Some(reason) => Err(IamError::Unavailable(reason.clone())),
+            None => Ok(()),
+        }
+    }
+
+    // Restores users, permissions and roles from a JSON store and keeps saving to it
+    pub fn load_from_path(path: impl Into<PathBuf>) -> Result<Self, IamError> {
+        let store = JsonFileStore::new(path);
+        let snapshot = store.load()?;
+
+        let manager = Self::new().with_store(store);
+        *manager.permissions.write()? = snapshot.permissions;
+        *manager.roles.write()? = snapshot.roles;
+        *manager.users.write()? = snapshot.users;
+        *manager.reserved_bits.write()? = snapshot.reserved_bits;
+        Ok(manager)
+    }
+
+    // Backend saved after every mutation (none by default, state is memory only)
+    pub fn with_store(mut self, store: impl Store + 'static) -> Self {
+        self.store = Some(Mutex::new(Persistence { store: Box::new(store), saved: None }));
+        self
+    }
+
+    // Maximum number of audit entries kept in memory
+    pub fn with_audit_capacity(mut self, capacity: usize) -> Self {
+        self.audit_capacity = capacity;
+        self
+    }
+
+    // Reject users created without any permission (disabled by default)
+    pub fn with_require_nonempty_permissions(mut self, enabled: bool) -> Self {
+        self.require_nonempty_permissions = enabled;
+        self
+    }
+
+    // UpdateUserPermissions may only add bits, never clear one the user holds (disabled by default)
+    pub fn with_additive_only(mut self, enabled: bool) -> Self {
+        self.additive_only = enabled;
+        self
+    }
+
+    // Order used for every permission name list returned by the manager
+    pub fn with_sort_order(mut self, order: SortOrder) -> Self {
+        self.sort_order = order;
+        self
+    }
+
+    // List a held composite instead of the permissions it covers (disabled by default)
+    pub fn with_collapse_composites(mut self, enabled: bool) -> Self {
+        self.collapse_composites = enabled;
+        self
+    }
+
+    // Failed RPCs return a gRPC status (NOT_FOUND, ALREADY_EXISTS, INVALID_ARGUMENT, ...) instead of
+    // an OK response with success: false (disabled by default)
+    pub fn with_status_codes(mut self, enabled: bool) -> Self {
+        self.status_codes = enabled;
+        self
+    }
+
+    // bcrypt cost for new and updated passwords (DEFAULT_COST by default, lower it to speed up tests)
+    pub fn with_bcrypt_cost(mut self, cost: u32) -> Result<Self, IamError> {
+        self.password_cost = validate_bcrypt_cost(cost)?;
+        self.dummy_password_hash = OnceLock::new();
+        Ok(self)
+    }
+
+    // Checked on every new or changed password (8 characters minimum by default)
+    pub fn with_password_policy(mut self, policy: PasswordPolicy) -> Self {
+        self.password_policy = policy;
+        self
+    }
+
+    // Policy limit on the registry size, below the 64-bit hard limit
+    pub fn with_max_permissions(mut self, max_permissions: usize) -> Self {
+        self.max_permissions = Some(max_permissions);
+        self
+    }
+
+    // Tenant prefix prepended to generated user ids ("acme:<uuid>")
+    pub fn with_id_prefix(mut self, prefix: String) -> Self {
+        self.id_prefix = Some(prefix);
+        self
+    }
+
+    // Named set of permissions (with their values) applied by ApplyPermissionTemplate
+    pub fn with_permission_template(mut self, name: String, permissions: Vec<Permission>) -> Self {
+        let permissions = permissions.into_iter()
+            .map(|p| Permission { name: normalize_permission_name(&p.name), ..p })
+            .collect();
+        self.templates.insert(name, permissions);
+        self
+    }
+
+    pub fn save_to_path(&self, path: impl Into<PathBuf>) -> Result<(), IamError> {
+        JsonFileStore::new(path).save(&self.snapshot()?)
+    }
+
+    fn snapshot(&self) -> Result<StoreSnapshot, IamError> {
+        let permissions = self.permissions.read()?;
+        let roles = self.roles.read()?;
+        let users = self.users.read()?;
+        let reserved_bits = self.reserved_bits.read()?;
+
+        Ok(StoreSnapshot {
+            users: users.clone(),
+            permissions: permissions.clone(),
+            roles: roles.clone(),
+            reserved_bits: *reserved_bits,
+        })
+    }
+
+    fn restore(&self, snapshot: &StoreSnapshot) -> Result<(), IamError> {
+        *self.permissions.write()? = snapshot.permissions.clone();
+        *self.roles.write()? = snapshot.roles.clone();
+        *self.users.write()? = snapshot.users.clone();
+        *self.reserved_bits.write()? = snapshot.reserved_bits;
+        Ok(())
+    }
+
+    // Called by every mutation before it takes any other lock. With a store, mutations then run
+    // one at a time, so the last saved snapshot is always the state the mutation started from.
+    fn begin_write(&self) -> Result<Option<MutexGuard<'_, Persistence>>, IamError> {
+        self.ensure_available()?;
+        let Some(store) = &self.store else {
+            return Ok(None);
+        };
+        let mut persistence = store.lock()?;
+        if persistence.saved.is_none() {
+            persistence.saved = Some(self.snapshot()?);
+        }
+        Ok(Some(persistence))
+    }
+
+    // Saves the state left by the mutation. When the save fails the mutation is rolled back,
+    // so memory never runs ahead of the store.
+    fn persist(&self, write: Option<MutexGuard<'_, Persistence>>) -> Result<(), IamError> {
+        let Some(mut persistence) = write else {
+            return Ok(());
+        };
+        let snapshot = self.snapshot()?;
+        if let Err(e) = persistence.store.save(&snapshot) {
+            if let Some(saved) = &persistence.saved {
+                self.restore(saved)?;
+            }
+            return Err(e);
+        }
+        persistence.saved = Some(snapshot);
+        Ok(())
+    }
+
+    fn record_audit(&self, action: AuditAction, user_id: &str, before: u64, after: u64) -> Result<(), IamError> {
+        let mut audit_log = self.audit_log.write()?;
+        if self.audit_capacity == 0 {
+            return Ok(());
+        }
+        while audit_log.len() >= self.audit_capacity {
+            audit_log.pop_front();
+        }
+        audit_log.push_back(AuditEntry {
+            timestamp: unix_timestamp(),
+            action,
+            user_id: user_id.to_string(),
+            before,
+            after,
+        });
+        Ok(())
+    }
+
+    fn permission_names(&self, permissions: &HashMap<String, Permission>, bits: u64) -> Result<Vec<String>, IamError> {
+        bits_to_permission_names_with_order(permissions, bits, self.sort_order, self.collapse_composites)
+    }
 
+    // Permission names for a raw mask, in the manager's sort order
+    pub fn permission_names_for(&self, bits: u64) -> Result<Vec<String>, IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        self.permission_names(&permissions, bits)
+    }
+
+    pub fn create_user(&self, name: String, email: String, password: String, permission_names: Vec<String>) -> Result<User, IamError> {
+        let user = self.prepare_user(name, email, password, permission_names)?;
+
+        let write = self.begin_write()?;
+        let permissions = self.permissions.read()?;
         // .write() - demande un verrou exclusif en écriture
         // Retourne Result<RwLockWriteGuard, PoisonError>
-        // .map_err() - convertit l'erreur PoisonError en String
-        // ? - propage l'erreur si le verrou échoue
-        let mut users = self.users.write().map_err(|_| "Failed to acquire write lock")?;
-        users.insert(user.id.clone(), user.clone());
+        // ? - convertit l'erreur PoisonError en IamError et la propage si le verrou échoue
+        let mut users = self.users.write()?;
+        Self::insert_user(&permissions, &mut users, user.clone())?;
+        drop(users);
+        drop(permissions);
+
+        self.persist(write)?;
+        self.record_audit(AuditAction::Create, &user.id, 0, user.permissions)?;
+        Ok(user)
+    }
+
+    // Creates every user in order. Each entry fails on its own unless `atomic` is set, in which case
+    // any failure leaves the store unchanged. Returns, in input order, the created user or the reason.
+    pub fn batch_create_users(&self, new_users: Vec<NewUser>, atomic: bool) -> Result<Vec<Result<User, IamError>>, IamError> {
+        // Validation and bcrypt hashing happen before any lock is taken
+        let prepared: Vec<Result<User, IamError>> = new_users.into_iter()
+            .map(|u| self.prepare_user(u.name, u.email, u.password, u.permissions))
+            .collect();
+
+        // Atomic batches still insert every valid entry, so each failing entry reports its own
+        // reason, then roll them all back
+        let write = self.begin_write()?;
+        let permissions = self.permissions.read()?;
+        let mut users = self.users.write()?;
+
+        let mut results = Vec::new();
+        for user in prepared {
+            let result = user.and_then(|user| Self::insert_user(&permissions, &mut users, user.clone()).map(|_| user));
+            results.push(result);
+        }
+
+        if atomic && results.iter().any(|result| result.is_err()) {
+            for user in results.iter().flatten() {
+                users.remove(&user.id);
+            }
+            return Ok(Self::rolled_back(results));
+        }
+        drop(users);
+        drop(permissions);
+
+        self.persist(write)?;
+        for user in results.iter().flatten() {
+            self.record_audit(AuditAction::Create, &user.id, 0, user.permissions)?;
+        }
+        Ok(results)
+    }
+
+    // Entries that would have succeeded report the rollback instead
+    fn rolled_back(results: Vec<Result<User, IamError>>) -> Vec<Result<User, IamError>> {
+        results.into_iter()
+            .map(|result| result.and(Err(IamError::FailedPrecondition("Not created: another entry of the atomic batch failed".to_string()))))
+            .collect()
+    }
+
+    // Validates the input and hashes the password, without touching the store
+    fn prepare_user(&self, name: String, email: String, password: String, permission_names: Vec<String>) -> Result<User, IamError> {
+        validate_email(&email)?;
+        self.password_policy.check(&password)?;
+        let permissions = self.permissions.read()?;
+        let permissions_bits = permission_names_to_bits(&permissions, &permission_names)?;
+        drop(permissions);
+
+        if self.require_nonempty_permissions && permissions_bits == 0 {
+            return Err(IamError::InvalidArgument("User must be granted at least one permission".to_string()));
+        }
 
+        let mut user = User::new_with_cost(name, email, password, permissions_bits, self.password_cost)?;
+        if let Some(prefix) = &self.id_prefix {
+            user.id = format!("{}:{}", prefix, user.id);
+        }
         Ok(user)
     }
 
+    // Checks that depend on the other users, run under the users write lock
+    fn insert_user(permissions: &HashMap<String, Permission>, users: &mut HashMap<String, User>, user: User) -> Result<(), IamError> {
+        let email_key = user.email.trim().to_lowercase();
+        if users.values().any(|u| u.email.trim().to_lowercase() == email_key) {
+            return Err(IamError::AlreadyExists("Email already registered".to_string()));
+        }
+        Self::enforce_max_holders(permissions, users, &user.id, user.permissions)?;
+        users.insert(user.id.clone(), user);
+        Ok(())
+    }
+
     pub fn get_user(&self, user_id: &str) -> Option<User> {
+        self.ensure_available().ok()?;
         let users = self.users.read().ok()?;
         users.get(user_id).cloned()
     }
 
-    pub fn get_user_with_permission_names(&self, user_id: &str) -> Result<Option<(User, Vec<String>)>, String> {
-        let users = self.users.read().map_err(|_| "Failed to acquire read lock")?;
+    // Returns the user, its permission names and the bits no longer present in the registry
+    pub fn get_user_with_permission_names(&self, user_id: &str) -> Result<Option<(User, Vec<String>, u64)>, IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let users = self.users.read()?;
 
         if let Some(user) = users.get(user_id) {
-            let permissions = self.permissions.read().map_err(|_| "Failed to acquire read lock")?;
-            let permission_names = bits_to_permission_names(&permissions, user.permissions)?;
-            Ok(Some((user.clone(), permission_names)))
+            let held = user.effective_permissions(unix_timestamp());
+            let permission_names = self.permission_names(&permissions, held)?;
+            let orphans = orphan_bits(&permissions, held);
+            Ok(Some((user.clone(), permission_names, orphans)))
         } else {
             Ok(None)
         }
     }
 
-    pub fn update_user_permissions(&self, user_id: &str, permission_names: Vec<String>) -> Result<User, String> {
-        let permissions = self.permissions.read().map_err(|_| "Failed to acquire read lock")?;
+    // GetUser by email (case-insensitive, surrounding whitespace ignored). Stores written before
+    // duplicate emails were rejected may hold several matches, the smallest id is returned.
+    pub fn get_user_by_email(&self, email: &str) -> Result<Option<(User, Vec<String>, u64)>, IamError> {
+        self.ensure_available()?;
+        let users = self.users.read()?;
+        let user_id = user_by_email(&users, email).map(|u| u.id.clone());
+        drop(users);
+
+        match user_id {
+            Some(user_id) => self.get_user_with_permission_names(&user_id),
+            None => Ok(None),
+        }
+    }
+
+    // Replaces only the given fields. A new password is re-hashed, a new email must be valid and not taken.
+    pub fn update_user(&self, user_id: &str, name: Option<String>, email: Option<String>, password: Option<String>) -> Result<User, IamError> {
+        if let Some(email) = &email {
+            validate_email(email)?;
+        }
+        if let Some(password) = &password {
+            self.password_policy.check(password)?;
+        }
+        let password_hash = match password {
+            Some(password) => Some(hash(password, self.password_cost).map_err(|e| IamError::Internal(format!("Failed to hash password: {}", e)))?),
+            None => None,
+        };
+        let write = self.begin_write()?;
+        let mut users = self.users.write()?;
+
+        if !users.contains_key(user_id) {
+            return Err(user_not_found());
+        }
+        if let Some(email) = &email {
+            let email_key = email.trim().to_lowercase();
+            if users.values().any(|u| u.id != user_id && u.email.trim().to_lowercase() == email_key) {
+                return Err(IamError::AlreadyExists("Email already registered".to_string()));
+            }
+        }
+
+        let user = users.get_mut(user_id).ok_or_else(user_not_found)?;
+        if let Some(name) = name {
+            user.name = name;
+        }
+        if let Some(email) = email {
+            user.email = email;
+        }
+        if let Some(password_hash) = password_hash {
+            user.password_hash = password_hash;
+        }
+        let user = user.clone();
+        drop(users);
+
+        self.persist(write)?;
+        self.record_audit(AuditAction::Update, &user.id, user.permissions, user.permissions)?;
+        Ok(user)
+    }
+
+    pub fn delete_user(&self, user_id: &str) -> Result<(), IamError> {
+        let write = self.begin_write()?;
+        let mut users = self.users.write()?;
+
+        let Some(user) = users.remove(user_id) else {
+            return Err(user_not_found());
+        };
+        drop(users);
+
+        self.persist(write)?;
+        self.record_audit(AuditAction::Delete, user_id, user.permissions, 0)
+    }
+
+    // One page of users sorted by id, starting after the id in page_token.
+    // Returns the users with their permission names and the next page token (empty on the last page).
+    pub fn list_users(&self, page_size: usize, page_token: &str) -> Result<(Vec<UserWithPermissions>, String), IamError> {
+        self.ensure_available()?;
+        let page_size = if page_size == 0 { DEFAULT_PAGE_SIZE } else { page_size };
+        let permissions = self.permissions.read()?;
+        let users = self.users.read()?;
+        users_page(&permissions, &users, page_size, page_token, self.sort_order, self.collapse_composites)
+    }
+
+    // Every user sorted by id, read chunk_size users at a time as the iterator advances
+    pub fn user_chunks(&self, chunk_size: usize) -> UserChunks {
+        UserChunks {
+            permissions: Arc::clone(&self.permissions),
+            users: Arc::clone(&self.users),
+            sort_order: self.sort_order,
+            collapse_composites: self.collapse_composites,
+            chunk_size: chunk_size.max(1),
+            next_token: Some(String::new()),
+            chunk: Vec::new().into_iter(),
+        }
+    }
+
+    // Every user sorted by id
+    pub fn all_users(&self) -> Result<Vec<UserWithPermissions>, IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let users = self.users.read()?;
+
+        let mut sorted: Vec<&User> = users.values().collect();
+        sorted.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut all = Vec::new();
+        let now = unix_timestamp();
+        for user in sorted {
+            let permission_names = self.permission_names(&permissions, user.effective_permissions(now))?;
+            all.push((user.clone(), permission_names));
+        }
+
+        Ok(all)
+    }
+
+    // Returns the user and its permission names when the password matches the stored hash.
+    // Every failure reports the same message so callers cannot probe for registered emails.
+    pub fn verify_credentials(&self, email: &str, password: &str) -> Result<(User, Vec<String>), IamError> {
+        self.ensure_available()?;
+        let user = user_by_email(&*self.users.read()?, email).cloned();
+
+        // bcrypt is slow on purpose, no lock is held while it runs
+        let password_hash = match &user {
+            Some(user) => user.password_hash.as_str(),
+            None => self.dummy_password_hash.get_or_init(|| hash("dummy-password", self.password_cost).unwrap_or_default()),
+        };
+        let matches = verify(password, password_hash).unwrap_or(false);
+
+        match user {
+            Some(user) if matches => {
+                let permissions = self.permissions.read()?;
+                let permission_names = self.permission_names(&permissions, user.effective_permissions(unix_timestamp()))?;
+                Ok((user, permission_names))
+            }
+            _ => Err(IamError::Unauthenticated("Invalid credentials".to_string())),
+        }
+    }
+
+    // Ties the given API key to an existing user
+    pub fn add_api_key(&self, key: String, user_id: &str) -> Result<(), IamError> {
+        if key.trim().is_empty() {
+            return Err(IamError::InvalidArgument("API key cannot be empty".to_string()));
+        }
+        let users = self.users.read()?;
+        if !users.contains_key(user_id) {
+            return Err(user_not_found());
+        }
+        let mut api_keys = self.api_keys.write()?;
+        if api_keys.contains_key(&key) {
+            return Err(IamError::AlreadyExists("API key already registered".to_string()));
+        }
+        api_keys.insert(key, user_id.to_string());
+        Ok(())
+    }
+
+    // Generates a random API key for the user and returns it
+    pub fn register_api_key(&self, user_id: &str) -> Result<String, IamError> {
+        let key = Uuid::new_v4().simple().to_string();
+        self.add_api_key(key.clone(), user_id)?;
+        Ok(key)
+    }
+
+    // The id of the user holding the key. Keys of deleted users are rejected.
+    pub fn resolve_api_key(&self, key: &str) -> Result<String, IamError> {
+        self.ensure_available()?;
+        let users = self.users.read()?;
+        let api_keys = self.api_keys.read()?;
+        match api_keys.get(key) {
+            Some(user_id) if users.contains_key(user_id) => Ok(user_id.clone()),
+            _ => Err(IamError::Unauthenticated("Invalid API key".to_string())),
+        }
+    }
+
+    // Admin view: the user, its permission names and the bcrypt cost of its stored password
+    pub fn describe_user(&self, user_id: &str) -> Result<(User, Vec<String>, u32), IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let users = self.users.read()?;
+        let user = users.get(user_id).ok_or_else(user_not_found)?;
+
+        let permission_names = self.permission_names(&permissions, user.effective_permissions(unix_timestamp()))?;
+        let password_cost = bcrypt_cost(&user.password_hash)?;
+
+        Ok((user.clone(), permission_names, password_cost))
+    }
+
+    // Everything kept about one user, for data subject requests. Roles are not recorded on users,
+    // so those whose permissions the user holds in full are reported. The returned user still
+    // carries its password hash, callers must leave it out.
+    pub fn export_user(&self, user_id: &str, include_audit: bool) -> Result<UserExport, IamError> {
+        self.ensure_available()?;
+        let (user, permission_names, role_names) = {
+            let permissions = self.permissions.read()?;
+            let roles = self.roles.read()?;
+            let users = self.users.read()?;
+            let user = users.get(user_id).ok_or_else(user_not_found)?;
+
+            let held = user.effective_permissions(unix_timestamp());
+            let permission_names = self.permission_names(&permissions, held)?;
+            let mut role_names: Vec<String> = roles.values()
+                .filter(|role| role.bits != 0 && compute_missing_bits(held, role.bits) == 0)
+                .map(|role| role.name.clone())
+                .collect();
+            role_names.sort();
+            (user.clone(), permission_names, role_names)
+        };
+
+        let audit_entries = if include_audit { self.get_audit_log(Some(user_id), 0)? } else { Vec::new() };
+        Ok((user, permission_names, role_names, audit_entries))
+    }
+
+    pub fn update_user_permissions(&self, user_id: &str, permission_names: Vec<String>) -> Result<PermissionUpdate, IamError> {
+        let write = self.begin_write()?;
+        let permissions = self.permissions.read()?;
         let permissions_bits = permission_names_to_bits(&permissions, &permission_names)?;
+        let mut users = self.users.write()?;
+
+        let Some(current) = users.get(user_id) else {
+            return Err(user_not_found());
+        };
+        if self.additive_only {
+            let removed_bits = compute_missing_bits(permissions_bits, current.permissions);
+            if removed_bits != 0 {
+                let mut removed = self.permission_names(&permissions, removed_bits)?;
+                removed.extend(split_bits(orphan_bits(&permissions, removed_bits)).iter().map(|value| value.to_string()));
+                return Err(IamError::FailedPrecondition(format!("Additive-only mode: update would remove held permissions ({}), revoke them explicitly instead", removed.join(", "))));
+            }
+        }
+        Self::enforce_max_holders(&permissions, &users, user_id, permissions_bits)?;
+
+        let user = users.get_mut(user_id).ok_or_else(user_not_found)?;
+        let before = user.permissions;
+        user.permissions = permissions_bits;
+        // Bits kept by the update stay temporary, added and removed ones lose their expiry
+        user.clear_expiries(!(before & permissions_bits));
+        let user = user.clone();
+        drop(users);
+
+        let added = self.permission_names(&permissions, permissions_bits & !before)?;
+        let removed = self.permission_names(&permissions, before & !permissions_bits)?;
+        drop(permissions);
+
+        self.persist(write)?;
+        self.record_audit(AuditAction::Update, user_id, before, user.permissions)?;
+        Ok((user, added, removed))
+    }
+
+    // ORs a single permission into the user's existing set. Returns the resulting permission names.
+    pub fn grant_permission(&self, user_id: &str, permission_name: &str) -> Result<Vec<String>, IamError> {
+        let write = self.begin_write()?;
+        let permissions = self.permissions.read()?;
+        let bit = permission_names_to_bits(&permissions, &[permission_name.to_string()])?;
+        let mut users = self.users.write()?;
+
+        if !users.contains_key(user_id) {
+            return Err(user_not_found());
+        }
+        Self::enforce_max_holders(&permissions, &users, user_id, bit)?;
+
+        let user = users.get_mut(user_id).ok_or_else(user_not_found)?;
+        let before = user.permissions;
+        user.permissions |= bit;
+        user.clear_expiries(bit);
+        let after = user.permissions;
+        let permission_names = self.permission_names(&permissions, user.effective_permissions(unix_timestamp()))?;
+        drop(users);
+        drop(permissions);
+
+        self.persist(write)?;
+        self.record_audit(AuditAction::Grant, user_id, before, after)?;
+        Ok(permission_names)
+    }
+
+    // Grants a permission that stops counting as held once `duration` has elapsed. Bits the user
+    // already holds permanently stay permanent. Returns the resulting permission names and the expiry.
+    pub fn grant_temporary_permission(&self, user_id: &str, permission_name: &str, duration: Duration) -> Result<(Vec<String>, u64), IamError> {
+        if duration.as_secs() == 0 {
+            return Err(IamError::InvalidArgument("Duration must be at least one second".to_string()));
+        }
+        let write = self.begin_write()?;
+        let permissions = self.permissions.read()?;
+        let bit = permission_names_to_bits(&permissions, &[permission_name.to_string()])?;
+        let mut users = self.users.write()?;
+
+        if !users.contains_key(user_id) {
+            return Err(user_not_found());
+        }
+        Self::enforce_max_holders(&permissions, &users, user_id, bit)?;
+
+        let now = unix_timestamp();
+        let expires_at = now.saturating_add(duration.as_secs());
+        let user = users.get_mut(user_id).ok_or_else(user_not_found)?;
+        let before = user.permissions;
+        for value in split_bits(bit) {
+            let permanent = user.permissions & value != 0 && !user.expiries.contains_key(&value);
+            if !permanent {
+                user.expiries.insert(value, expires_at);
+            }
+        }
+        user.permissions |= bit;
+        let after = user.permissions;
+        let permission_names = self.permission_names(&permissions, user.effective_permissions(now))?;
+        drop(users);
+        drop(permissions);
+
+        self.persist(write)?;
+        self.record_audit(AuditAction::Grant, user_id, before, after)?;
+        Ok((permission_names, expires_at))
+    }
+
+    // Clears a single permission from the user's existing set. Returns the resulting permission names.
+    pub fn revoke_permission(&self, user_id: &str, permission_name: &str) -> Result<Vec<String>, IamError> {
+        let write = self.begin_write()?;
+        let permissions = self.permissions.read()?;
+        let bit = permission_names_to_bits(&permissions, &[permission_name.to_string()])?;
+        let mut users = self.users.write()?;
+
+        let user = users.get_mut(user_id).ok_or_else(user_not_found)?;
+        let before = user.permissions;
+        user.permissions &= !bit;
+        user.clear_expiries(bit);
+        let after = user.permissions;
+        let permission_names = self.permission_names(&permissions, user.effective_permissions(unix_timestamp()))?;
+        drop(users);
+        drop(permissions);
+
+        self.persist(write)?;
+        self.record_audit(AuditAction::Revoke, user_id, before, after)?;
+        Ok(permission_names)
+    }
+
+    // Clears every bit the user holds, temporary grants included, keeping the account
+    pub fn reset_user_permissions(&self, user_id: &str) -> Result<Vec<String>, IamError> {
+        let write = self.begin_write()?;
+        let mut users = self.users.write()?;
+
+        let user = users.get_mut(user_id).ok_or_else(user_not_found)?;
+        let before = user.permissions;
+        user.permissions = 0;
+        user.expiries.clear();
+        drop(users);
+
+        self.persist(write)?;
+        self.record_audit(AuditAction::Reset, user_id, before, 0)?;
+        Ok(Vec::new())
+    }
+
+    // Grants the same permissions to every listed user under a single write lock.
+    // Returns, per user id, the resulting permission names or the reason it failed.
+    pub fn batch_grant_permissions(&self, user_ids: &[String], permission_names: &[String]) -> Result<Vec<BatchUserResult>, IamError> {
+        let write = self.begin_write()?;
+        let permissions = self.permissions.read()?;
+        let granted_bits = permission_names_to_bits(&permissions, permission_names)?;
+        let mut users = self.users.write()?;
+
+        let mut results = Vec::new();
+        let mut changes = Vec::new();
+        for user_id in user_ids {
+            if !users.contains_key(user_id) {
+                results.push((user_id.clone(), Err(user_not_found())));
+                continue;
+            }
+            if let Err(e) = Self::enforce_max_holders(&permissions, &users, user_id, granted_bits) {
+                results.push((user_id.clone(), Err(e)));
+                continue;
+            }
+
+            if let Some(user) = users.get_mut(user_id) {
+                let before = user.permissions;
+                user.permissions |= granted_bits;
+                user.clear_expiries(granted_bits);
+                let permission_names = self.permission_names(&permissions, user.effective_permissions(unix_timestamp()))?;
+                changes.push((user_id, before, user.permissions));
+                results.push((user_id.clone(), Ok(permission_names)));
+            }
+        }
+        drop(users);
         drop(permissions);
-        let mut users = self.users.write().map_err(|_| "Failed to acquire write lock")?;
 
-        if let Some(user) = users.get_mut(user_id) {
-            user.permissions = permissions_bits;
-            Ok(user.clone())
-        } else {
-            Err("User not found".to_string())
+        self.persist(write)?;
+        for (user_id, before, after) in changes {
+            self.record_audit(AuditAction::Grant, user_id, before, after)?;
+        }
+        Ok(results)
+    }
+
+    // Rejects the grant when a capped permission already has max_holders other holders
+    fn enforce_max_holders(
+        permissions: &HashMap<String, Permission>,
+        users: &HashMap<String, User>,
+        user_id: &str,
+        granted_bits: u64,
+    ) -> Result<(), IamError> {
+        // Holders whose temporary grant has expired no longer count
+        let now = unix_timestamp();
+        for permission in permissions.values() {
+            let Some(max_holders) = permission.max_holders else {
+                continue;
+            };
+            if granted_bits & permission.value == 0 {
+                continue;
+            }
+
+            let holders = users.values()
+                .filter(|u| u.id != user_id && u.effective_permissions(now) & permission.value != 0)
+                .count();
+            if holders >= max_holders as usize {
+                return Err(IamError::ResourceExhausted(format!("Permission '{}' has reached its maximum of {} holders", permission.name, max_holders)));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Rejects a change that would leave more permissions registered than max_permissions allows
+    fn check_registry_size(&self, size: usize) -> Result<(), IamError> {
+        match self.max_permissions {
+            Some(max_permissions) if size > max_permissions =>
+                Err(IamError::ResourceExhausted(format!("Permission registry is full (maximum {} permissions)", max_permissions))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn add_permission(&self, name: String, label: String, description: String, max_holders: Option<u32>, requested_value: Option<u64>) -> Result<Permission, IamError> {
+        let name = validate_permission_name(&name)?;
+        let write = self.begin_write()?;
+        let mut permissions = self.permissions.write()?;
+        let mut reserved_bits = self.reserved_bits.write()?;
+
+        if permissions.contains_key(&name) {
+            return Err(IamError::AlreadyExists("Permission already exists".to_string()));
+        }
+
+        self.check_registry_size(permissions.len() + 1)?;
+
+        let next_value = match requested_value {
+            Some(value) => {
+                if !value.is_power_of_two() {
+                    return Err(IamError::InvalidArgument(format!("Value {} is not a single permission bit", value)));
+                }
+                if let Some(existing) = permissions.values().find(|p| p.value == value) {
+                    return Err(IamError::AlreadyExists(format!("Value {} is already used by '{}'", value, existing.name)));
+                }
+                value
+            }
+            None => {
+                let mut used_values = permissions.values().filter(|p| !p.composite).map(|p| p.value).collect::<Vec<_>>();
+                used_values.extend(split_bits(*reserved_bits));
+                find_next_available_bit(&used_values)?
+            }
+        };
+        // Claiming a reserved bit releases the reservation
+        *reserved_bits &= !next_value;
+
+        let permission = Permission {
+            label,
+            max_holders,
+            sequence: next_sequence(&permissions),
+            ..Permission::new_with_description(name.clone(), next_value, description)
+        };
+
+        permissions.insert(name, permission.clone());
+        drop(reserved_bits);
+        drop(permissions);
+
+        self.persist(write)?;
+        Ok(permission)
+    }
+
+    // Registers a name standing for the union of existing permissions' bits. Granting it grants
+    // every member, and it is reported in a user's permissions whenever all members are held.
+    pub fn add_composite_permission(&self, name: String, members: Vec<String>, label: String, description: String) -> Result<Permission, IamError> {
+        let name = validate_permission_name(&name)?;
+        let write = self.begin_write()?;
+        let mut permissions = self.permissions.write()?;
+
+        if permissions.contains_key(&name) {
+            return Err(IamError::AlreadyExists("Permission already exists".to_string()));
+        }
+        self.check_registry_size(permissions.len() + 1)?;
+        let value = permission_names_to_bits(&permissions, &members)?;
+        if value.count_ones() < 2 {
+            return Err(IamError::InvalidArgument("A composite permission needs at least two member bits".to_string()));
+        }
+        if let Some(existing) = permissions.values().find(|p| p.composite && p.value == value) {
+            return Err(IamError::AlreadyExists(format!("Value {} is already used by composite '{}'", value, existing.name)));
+        }
+
+        let permission = Permission {
+            label,
+            sequence: next_sequence(&permissions),
+            ..Permission::new_composite(name.clone(), value, description)
+        };
+
+        permissions.insert(name, permission.clone());
+        drop(permissions);
+
+        self.persist(write)?;
+        Ok(permission)
+    }
+
+    // Registers every template permission not already present, all or nothing.
+    // Returns the added permissions and the names skipped because they already exist.
+    pub fn apply_permission_template(&self, template_name: &str) -> Result<(Vec<Permission>, Vec<String>), IamError> {
+        let template = self.templates.get(template_name)
+            .ok_or_else(|| IamError::NotFound(format!("Permission template '{}' not found", template_name)))?;
+        let write = self.begin_write()?;
+        let mut permissions = self.permissions.write()?;
+
+        let mut added = Vec::new();
+        let mut skipped = Vec::new();
+        for permission in template {
+            if permissions.contains_key(&permission.name) {
+                skipped.push(permission.name.clone());
+                continue;
+            }
+            if !permission.value.is_power_of_two() {
+                return Err(IamError::InvalidArgument(format!("Template permission '{}' has value {} which is not a single bit", permission.name, permission.value)));
+            }
+            let collision = permissions.values()
+                .chain(added.iter())
+                .find(|p| p.value == permission.value);
+            if let Some(existing) = collision {
+                return Err(IamError::AlreadyExists(format!("Template permission '{}' value {} is already used by '{}'", permission.name, permission.value, existing.name)));
+            }
+            added.push(permission.clone());
+        }
+
+        self.check_registry_size(permissions.len() + added.len())?;
+
+        let mut reserved_bits = self.reserved_bits.write()?;
+        for permission in &mut added {
+            *reserved_bits &= !permission.value;
+            permission.sequence = next_sequence(&permissions);
+            permissions.insert(permission.name.clone(), permission.clone());
+        }
+        drop(reserved_bits);
+        drop(permissions);
+
+        self.persist(write)?;
+        Ok((added, skipped))
+    }
+
+    // Marks values as reserved so automatic allocation skips them. Returns every reserved value.
+    pub fn reserve_bits(&self, values: &[u64]) -> Result<Vec<u64>, IamError> {
+        let write = self.begin_write()?;
+        let permissions = self.permissions.read()?;
+        let mut reserved_bits = self.reserved_bits.write()?;
+
+        let mut requested = 0u64;
+        for &value in values {
+            if !value.is_power_of_two() {
+                return Err(IamError::InvalidArgument(format!("Value {} is not a single permission bit", value)));
+            }
+            if let Some(existing) = permissions.values().find(|p| p.value == value) {
+                return Err(IamError::AlreadyExists(format!("Value {} is already used by '{}'", value, existing.name)));
+            }
+            requested |= value;
+        }
+
+        *reserved_bits |= requested;
+        let reserved_values = split_bits(*reserved_bits);
+        drop(reserved_bits);
+        drop(permissions);
+
+        self.persist(write)?;
+        Ok(reserved_values)
+    }
+
+    pub fn set_permission_label(&self, name: &str, label: String) -> Result<Permission, IamError> {
+        let write = self.begin_write()?;
+        let mut permissions = self.permissions.write()?;
+
+        let permission = permissions.get_mut(&normalize_permission_name(name)).ok_or_else(permission_not_found)?;
+        permission.label = label;
+        let permission = permission.clone();
+        drop(permissions);
+
+        self.persist(write)?;
+        Ok(permission)
+    }
+
+    // Keeps the value, so every user holding the bit shows the new name
+    pub fn rename_permission(&self, old_name: &str, new_name: &str) -> Result<Permission, IamError> {
+        let renamed = self.batch_rename_permissions(&[(old_name.to_string(), new_name.to_string())])?;
+        renamed.into_iter().next().ok_or_else(permission_not_found)
+    }
+
+    // Renames every (old, new) pair under a single write lock, all or nothing.
+    // Values are kept, so users holding the bits are unaffected.
+    pub fn batch_rename_permissions(&self, renames: &[(String, String)]) -> Result<Vec<Permission>, IamError> {
+        let mut validated = Vec::new();
+        for (old_name, new_name) in renames {
+            validated.push((normalize_permission_name(old_name.trim()), validate_permission_name(new_name)?));
+        }
+        let renames = validated;
+        let write = self.begin_write()?;
+        let mut permissions = self.permissions.write()?;
+
+        let mut old_names: Vec<&str> = Vec::new();
+        let mut new_names: Vec<&str> = Vec::new();
+        for (old_name, new_name) in &renames {
+            if !permissions.contains_key(old_name) {
+                return Err(IamError::NotFound(format!("Permission '{}' not found", old_name)));
+            }
+            if old_names.contains(&old_name.as_str()) {
+                return Err(IamError::InvalidArgument(format!("Permission '{}' is renamed more than once", old_name)));
+            }
+            if new_names.contains(&new_name.as_str()) {
+                return Err(IamError::InvalidArgument(format!("Several permissions are renamed to '{}'", new_name)));
+            }
+            old_names.push(old_name);
+            new_names.push(new_name);
+        }
+
+        // A target may reuse a name only if that permission is itself renamed away
+        for (old_name, new_name) in &renames {
+            if permissions.contains_key(new_name) && !old_names.contains(&new_name.as_str()) {
+                return Err(IamError::AlreadyExists(format!("Cannot rename '{}' to '{}': permission already exists", old_name, new_name)));
+            }
+        }
+
+        let moved: Vec<(String, Permission)> = renames.iter()
+            .filter_map(|(old_name, new_name)| permissions.remove(old_name).map(|p| (new_name.clone(), p)))
+            .collect();
+
+        let mut renamed = Vec::new();
+        for (new_name, mut permission) in moved {
+            permission.name = new_name.clone();
+            permissions.insert(new_name, permission.clone());
+            renamed.push(permission);
+        }
+        drop(permissions);
+
+        self.persist(write)?;
+        Ok(renamed)
+    }
+
+    // Clears the bit from every user and role so it can be reallocated without granting anything
+    pub fn remove_permission(&self, name: &str) -> Result<(), IamError> {
+        let write = self.begin_write()?;
+        let mut permissions = self.permissions.write()?;
+        let mut roles = self.roles.write()?;
+        let mut users = self.users.write()?;
+
+        let name = normalize_permission_name(name);
+        let Some(permission) = permissions.get(&name) else {
+            return Err(permission_not_found());
+        };
+        if !permission.composite {
+            if let Some(composite) = permissions.values().find(|p| p.composite && p.value & permission.value != 0) {
+                return Err(IamError::FailedPrecondition(format!("Permission '{}' is a member of composite '{}'", permission.name, composite.name)));
+            }
+        }
+        let Some(permission) = permissions.remove(&name) else {
+            return Err(permission_not_found());
+        };
+        // A composite owns no bits of its own, so users and roles keep its members
+        if !permission.composite {
+            for user in users.values_mut() {
+                user.permissions &= !permission.value;
+                user.clear_expiries(permission.value);
+            }
+            for role in roles.values_mut() {
+                role.bits &= !permission.value;
+            }
+        }
+        drop(users);
+        drop(roles);
+        drop(permissions);
+
+        self.persist(write)
+    }
+
+    pub fn list_permissions(&self) -> Result<Vec<Permission>, IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let mut perms: Vec<Permission> = permissions.values().cloned().collect();
+        perms.sort_by_key(|p| p.value);
+        Ok(perms)
+    }
+
+    // Complete catalog, in the form accepted by import_permissions
+    pub fn export_permissions(&self) -> Result<Vec<Permission>, IamError> {
+        self.ensure_available()?;
+        self.list_permissions()
+    }
+
+    // Registers the given permissions with their own values, all or nothing, so user masks mean
+    // the same in both environments. Returns the imported permissions and the names skipped because
+    // they are already registered with the same value. Any other clash is returned as an error.
+    pub fn import_permissions(&self, incoming: Vec<Permission>) -> Result<(Vec<Permission>, Vec<String>), IamError> {
+        let mut registry = PermissionRegistry::new();
+        for (sequence, permission) in incoming.into_iter().enumerate() {
+            let name = validate_permission_name(&permission.name)?;
+            if registry.contains_key(&name) {
+                return Err(IamError::InvalidArgument(format!("Permission '{}' appears more than once in the import", name)));
+            }
+            let created_at = if permission.created_at == 0 { unix_timestamp() } else { permission.created_at };
+            registry.insert(name.clone(), Permission { name, created_at, sequence: sequence as u64, ..permission });
+        }
+
+        let write = self.begin_write()?;
+        let mut permissions = self.permissions.write()?;
+        let mut reserved_bits = self.reserved_bits.write()?;
+
+        let merged = merge_registries(&permissions, &registry)
+            .map_err(|conflicts| IamError::Conflicts(conflicts.iter().map(|c| c.detail()).collect()))?;
+        validate_permission_values(&merged).map_err(IamError::Conflicts)?;
+        self.check_registry_size(merged.len())?;
+
+        let mut imported: Vec<Permission> = merged.values()
+            .filter(|p| !permissions.contains_key(&p.name))
+            .cloned()
+            .collect();
+        imported.sort_by_key(|p| p.value);
+        let mut skipped: Vec<String> = registry.keys()
+            .filter(|name| permissions.contains_key(*name))
+            .cloned()
+            .collect();
+        skipped.sort();
+
+        // Imported bits are claimed, releasing any reservation on them
+        for permission in imported.iter().filter(|p| !p.composite) {
+            *reserved_bits &= !permission.value;
+        }
+        *permissions = merged;
+        drop(reserved_bits);
+        drop(permissions);
+
+        self.persist(write)?;
+        Ok((imported, skipped))
+    }
+
+    pub fn get_permission_by_value(&self, value: u64) -> Result<Permission, IamError> {
+        self.ensure_available()?;
+        if !value.is_power_of_two() {
+            return Err(IamError::InvalidArgument(format!("Value {} is not a single permission bit", value)));
+        }
+
+        let permissions = self.permissions.read()?;
+        permissions.values()
+            .find(|p| p.value == value)
+            .cloned()
+            .ok_or_else(permission_not_found)
+    }
+
+    // Returns the permission and the number of users currently holding it
+    pub fn describe_permission(&self, name: &str) -> Result<PermissionDescription, IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let permission = permissions.get(&normalize_permission_name(name)).cloned().ok_or_else(permission_not_found)?;
+        let roles = self.roles.read()?;
+        let users = self.users.read()?;
+
+        // A composite is only held by users holding all of its bits, expired grants are not held
+        let now = unix_timestamp();
+        let holders = users.values()
+            .filter(|u| u.effective_permissions(now) & permission.value == permission.value)
+            .count();
+
+        let mut composites: Vec<String> = permissions.values()
+            .filter(|p| p.composite && p.name != permission.name && p.value & permission.value == permission.value)
+            .map(|p| p.name.clone())
+            .collect();
+        composites.sort();
+        let mut role_names: Vec<String> = roles.values()
+            .filter(|r| r.bits & permission.value == permission.value)
+            .map(|r| r.name.clone())
+            .collect();
+        role_names.sort();
+
+        Ok((permission, holders, composites, role_names))
+    }
+
+    // Permissions whose name starts with the prefix (case-insensitive), sorted by name.
+    // A limit of 0 returns every match.
+    pub fn search_permissions(&self, prefix: &str, limit: usize) -> Result<Vec<Permission>, IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let prefix = prefix.to_lowercase();
+
+        let mut matched: Vec<Permission> = permissions.values()
+            .filter(|p| p.name.to_lowercase().starts_with(&prefix))
+            .cloned()
+            .collect();
+        matched.sort_by(|a, b| a.name.cmp(&b.name));
+        if limit > 0 {
+            matched.truncate(limit);
+        }
+
+        Ok(matched)
+    }
+
+    pub fn create_role(&self, name: String, permission_names: &[String]) -> Result<Role, IamError> {
+        let write = self.begin_write()?;
+        let permissions = self.permissions.read()?;
+        let bits = permission_names_to_bits(&permissions, permission_names)?;
+        let mut roles = self.roles.write()?;
+
+        if roles.contains_key(&name) {
+            return Err(IamError::AlreadyExists("Role already exists".to_string()));
+        }
+
+        let role = Role::new(name.clone(), bits);
+        roles.insert(name, role.clone());
+        drop(roles);
+        drop(permissions);
+
+        self.persist(write)?;
+        Ok(role)
+    }
+
+    // Returns every role with its permission names, sorted by role name
+    pub fn list_roles(&self) -> Result<Vec<(Role, Vec<String>)>, IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let roles = self.roles.read()?;
+
+        let mut listed = Vec::new();
+        for role in roles.values() {
+            let permission_names = self.permission_names(&permissions, role.bits)?;
+            listed.push((role.clone(), permission_names));
+        }
+
+        listed.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        Ok(listed)
+    }
+
+    // ORs the role's bits into the user's existing permissions
+    pub fn assign_role(&self, user_id: &str, role_name: &str) -> Result<User, IamError> {
+        let write = self.begin_write()?;
+        let permissions = self.permissions.read()?;
+        let roles = self.roles.read()?;
+        let role = roles.get(role_name).ok_or_else(|| IamError::NotFound("Role not found".to_string()))?;
+        let mut users = self.users.write()?;
+
+        if !users.contains_key(user_id) {
+            return Err(user_not_found());
+        }
+        Self::enforce_max_holders(&permissions, &users, user_id, role.bits)?;
+
+        let user = users.get_mut(user_id).ok_or_else(user_not_found)?;
+        let before = user.permissions;
+        user.permissions |= role.bits;
+        user.clear_expiries(role.bits);
+        let user = user.clone();
+        drop(users);
+        drop(roles);
+        drop(permissions);
+
+        self.persist(write)?;
+        self.record_audit(AuditAction::Grant, user_id, before, user.permissions)?;
+        Ok(user)
+    }
+
+    // In Any mode the check passes when the user holds at least one required permission
+    // (or none are required). Missing permissions are only listed when the check fails.
+    // An empty requirement is vacuously satisfied in both modes, but only once the user is known to exist
+    pub fn check_permissions(&self, user_id: &str, required_permission_names: &[String], mode: MatchMode) -> Result<(bool, Vec<String>), IamError> {
+        // Exemple complet
+        // Utilisateur avec READ(1) + WRITE(2) = 3
+        // Demande READ(1) + DELETE(8) = 9
+        // required_bits = 9           // 1001
+        // user.permissions = 3        // 0011
+        // has_all = (3 & 9) == 9     // (0011 & 1001) == 1001 → 1 == 9 → false
+        // missing_bits = 9 & !3      // 1001 & 1100 = 1000 (DELETE)
+
+        // Résultat: (false, ["DELETE"])
+        // Fail closed: no user is granted anything while the registry is missing
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let users = self.users.read()?;
+
+        if let Some(user) = users.get(user_id) {
+            if required_permission_names.is_empty() {
+                return Ok((true, Vec::new()));
+            }
+
+            // BILLING_* requires every BILLING_ permission in All mode, any of them in Any mode
+            let required_names = expand_permission_wildcards(&permissions, required_permission_names)?;
+            let required_bits = permission_names_to_bits(&permissions, &required_names)?;
+            // Expired temporary grants count as not held
+            let held = user.effective_permissions(unix_timestamp());
+            let has_permissions = match mode {
+                MatchMode::All => (held & required_bits) == required_bits,
+                MatchMode::Any => required_bits == 0 || (held & required_bits) != 0,
+            };
+
+            // Find missing permissions
+            let missing_bits = compute_missing_bits(held, required_bits);
+            // ! = not bitwise pour inverser les bits
+            // Exemple :
+            // required_bits = 11          // 1011 (READ|WRITE|DELETE)
+            // user.permissions = 3        // 0011 (READ|WRITE)
+
+            // !user.permissions = ~3      // 1100 (tous les bits sauf read/write)
+            // missing_bits = 11 & ~3 = 8  // 1011 & 1100 = 1000 (DELETE manquant)
+            let missing_permissions = if missing_bits == 0 || has_permissions {
+                Vec::new()
+            } else {
+                self.permission_names(&permissions, missing_bits)?
+            };
+
+            Ok((has_permissions, missing_permissions))
+        } else {
+            Err(user_not_found())
+        }
+    }
+
+    // Same check as check_permissions (All mode) on a raw mask, without resolving names
+    pub fn check_permissions_by_mask(&self, user_id: &str, required_mask: u64) -> Result<(bool, u64), IamError> {
+        self.ensure_available()?;
+        let users = self.users.read()?;
+        let user = users.get(user_id).ok_or_else(user_not_found)?;
+
+        let held = user.effective_permissions(unix_timestamp());
+        let has_all = (held & required_mask) == required_mask;
+        Ok((has_all, compute_missing_bits(held, required_mask)))
+    }
+
+    // The user's raw bitmask and the registry mask, for clients caching bits instead of names
+    pub fn get_user_permission_mask(&self, user_id: &str) -> Result<(u64, u64), IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let users = self.users.read()?;
+        let user = users.get(user_id).ok_or_else(user_not_found)?;
+
+        Ok((user.effective_permissions(unix_timestamp()), registry_mask(&permissions)))
+    }
+
+    // Every registered permission not in the given set ("everything except")
+    pub fn get_complement(&self, permission_names: &[String]) -> Result<Vec<String>, IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let bits = permission_names_to_bits(&permissions, permission_names)?;
+        let complement = permissions_complement(bits, registry_mask(&permissions));
+        self.permission_names(&permissions, complement)
+    }
+
+    pub fn get_users_missing_permission(&self, permission_name: &str) -> Result<Vec<(User, Vec<String>)>, IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let required_bits = permission_names_to_bits(&permissions, &[permission_name.to_string()])?;
+        let users = self.users.read()?;
+
+        let now = unix_timestamp();
+        let mut missing = Vec::new();
+        for user in users.values() {
+            let held = user.effective_permissions(now);
+            if compute_missing_bits(held, required_bits) != 0 {
+                let permission_names = self.permission_names(&permissions, held)?;
+                missing.push((user.clone(), permission_names));
+            }
+        }
+
+        missing.sort_by(|(a, _), (b, _)| a.id.cmp(&b.id));
+        Ok(missing)
+    }
+
+
+    // Most recent audit entries in chronological order, optionally for one user.
+    // A limit of 0 returns every retained entry. Masks are rendered as permission names.
+    pub fn get_audit_log(&self, user_id: Option<&str>, limit: usize) -> Result<Vec<RenderedAuditEntry>, IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let audit_log = self.audit_log.read()?;
+
+        let mut matched: Vec<&AuditEntry> = audit_log.iter()
+            .rev()
+            .filter(|entry| match user_id {
+                Some(id) => entry.user_id == id,
+                None => true,
+            })
+            .collect();
+        if limit > 0 {
+            matched.truncate(limit);
+        }
+        matched.reverse();
+
+        let mut entries = Vec::new();
+        for entry in matched {
+            let before = self.permission_names(&permissions, entry.before)?;
+            let after = self.permission_names(&permissions, entry.after)?;
+            entries.push((entry.clone(), before, after));
+        }
+
+        Ok(entries)
+    }
+
+    // Permission check counters recorded by the CheckPermissions, CheckPermissionsByMask and
+    // BatchCheckPermissions handlers
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    // Reports store invariant violations without fixing them
+    pub fn check_integrity(&self) -> Result<Vec<IntegrityViolation>, IamError> {
+        self.ensure_available()?;
+        let permissions = self.permissions.read()?;
+        let users = self.users.read()?;
+        let mut violations = Vec::new();
+
+        if let Err(errors) = validate_permission_values(&permissions) {
+            for detail in errors {
+                violations.push(IntegrityViolation {
+                    kind: IntegrityViolationKind::PermissionValue,
+                    subject: "registry".to_string(),
+                    detail,
+                });
+            }
+        }
+
+        let mut sorted_users: Vec<&User> = users.values().collect();
+        sorted_users.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut emails: HashMap<String, &str> = HashMap::new();
+        for user in sorted_users {
+            let orphans = orphan_bits(&permissions, user.permissions);
+            if orphans != 0 {
+                violations.push(IntegrityViolation {
+                    kind: IntegrityViolationKind::OrphanBits,
+                    subject: user.id.clone(),
+                    detail: format!("User holds unregistered bits {:?}", split_bits(orphans)),
+                });
+            }
+
+            let email_key = user.email.trim().to_lowercase();
+            if let Some(other) = emails.insert(email_key.clone(), &user.id) {
+                violations.push(IntegrityViolation {
+                    kind: IntegrityViolationKind::DuplicateEmail,
+                    subject: email_key,
+                    detail: format!("Email shared by users {} and {}", other, user.id),
+                });
+            }
+        }
+
+        Ok(violations)
+    }
+
+    // Clears orphan bits from every user. Returns one violation per user that was repaired.
+    // Duplicate emails are left to an operator, and there is no email index to rebuild since
+    // lookups by email scan the users.
+    pub fn repair_integrity(&self) -> Result<Vec<IntegrityViolation>, IamError> {
+        let write = self.begin_write()?;
+        let repaired = Self::clear_orphan_bits(&self.users, &self.permissions)?;
+        if !repaired.is_empty() {
+            self.persist(write)?;
+        }
+        Ok(repaired)
+    }
+
+    // Runs repair_integrity every interval on a background thread, logging what it fixed. Repairs are
+    // saved to the store like any other mutation. The thread stops once the manager is dropped.
+    pub fn spawn_integrity_repair(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let manager = Arc::downgrade(self);
+
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            let Some(manager) = manager.upgrade() else {
+                break;
+            };
+            match manager.repair_integrity() {
+                Ok(repaired) => {
+                    for violation in repaired {
+                        log::info!("Repaired {} {}: {}", violation.kind.as_str(), violation.subject, violation.detail);
+                    }
+                }
+                Err(e) => log::warn!("Integrity repair failed: {}", e),
+            }
+        })
+    }
+
+    fn clear_orphan_bits(
+        users: &RwLock<HashMap<String, User>>,
+        permissions: &RwLock<HashMap<String, Permission>>,
+    ) -> Result<Vec<IntegrityViolation>, IamError> {
+        let permissions = permissions.read()?;
+        let mut users = users.write()?;
+
+        let mut repaired = Vec::new();
+        for user in users.values_mut() {
+            let orphans = orphan_bits(&permissions, user.permissions);
+            if orphans != 0 {
+                user.permissions &= !orphans;
+                user.clear_expiries(orphans);
+                repaired.push(IntegrityViolation {
+                    kind: IntegrityViolationKind::OrphanBits,
+                    subject: user.id.clone(),
+                    detail: format!("Cleared unregistered bits {:?}", split_bits(orphans)),
+                });
+            }
+        }
+
+        repaired.sort_by(|a, b| a.subject.cmp(&b.subject));
+        Ok(repaired)
+    }
+}
+
+#[tonic::async_trait]
+impl IamService for IamManager {
+    async fn create_user(
+        &self,
+        request: Request<CreateUserRequest>,
+    ) -> Result<Response<CreateUserResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.create_user(req.name, req.email, req.password, req.permissions) {
+            Ok(user) => {
+                let permission_names = self.permission_names_for(user.effective_permissions(unix_timestamp())).unwrap_or_default();
+
+                let response = CreateUserResponse {
+                    success: true,
+                    message: "User created successfully".to_string(),
+                    user: Some(crate::iam::User {
+                        id: user.id,
+                        name: user.name,
+                        email: user.email,
+                        permissions: permission_names,
+                    }),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = CreateUserResponse {
+                    success: false,
+                    message: e.to_string(),
+                    user: None,
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn batch_create_users(
+        &self,
+        request: Request<BatchCreateUsersRequest>,
+    ) -> Result<Response<BatchCreateUsersResponse>, Status> {
+        let req = request.into_inner();
+
+        let emails: Vec<String> = req.users.iter().map(|u| u.email.clone()).collect();
+        let new_users = req.users.into_iter().map(|u| NewUser {
+            name: u.name,
+            email: u.email,
+            password: u.password,
+            permissions: u.permissions,
+        }).collect();
+
+        match self.batch_create_users(new_users, req.atomic) {
+            Ok(results) => {
+                let now = unix_timestamp();
+                let created = results.iter().filter(|result| result.is_ok()).count();
+                let response = BatchCreateUsersResponse {
+                    success: true,
+                    message: format!("Created {} of {} users", created, results.len()),
+                    results: emails.into_iter().zip(results).map(|(email, result)| match result {
+                        Ok(user) => BatchCreateResult {
+                            email,
+                            success: true,
+                            message: "User created successfully".to_string(),
+                            user: Some(crate::iam::User {
+                                permissions: self.permission_names_for(user.effective_permissions(now)).unwrap_or_default(),
+                                id: user.id,
+                                name: user.name,
+                                email: user.email,
+                            }),
+                        },
+                        Err(e) => BatchCreateResult {
+                            email,
+                            success: false,
+                            message: e.to_string(),
+                            user: None,
+                        },
+                    }).collect(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = BatchCreateUsersResponse {
+                    success: false,
+                    message: e.to_string(),
+                    results: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn get_user(
+        &self,
+        request: Request<GetUserRequest>,
+    ) -> Result<Response<GetUserResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.get_user_with_permission_names(&req.user_id) {
+            Ok(Some((user, permission_names, orphans))) => {
+                let response = GetUserResponse {
+                    success: true,
+                    message: "User found".to_string(),
+                    user: Some(crate::iam::User {
+                        id: user.id,
+                        name: user.name,
+                        email: user.email,
+                        permissions: permission_names,
+                    }),
+                    has_orphan_bits: orphans != 0,
+                    orphan_bit_values: split_bits(orphans),
+                };
+                Ok(Response::new(response))
+            }
+            Ok(None) => {
+                if self.status_codes {
+                    return Err(Status::not_found("User not found"));
+                }
+                let response = GetUserResponse {
+                    success: false,
+                    message: "User not found".to_string(),
+                    user: None,
+                    has_orphan_bits: false,
+                    orphan_bit_values: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = GetUserResponse {
+                    success: false,
+                    message: e.to_string(),
+                    user: None,
+                    has_orphan_bits: false,
+                    orphan_bit_values: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn get_user_by_email(
+        &self,
+        request: Request<GetUserByEmailRequest>,
+    ) -> Result<Response<GetUserResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.get_user_by_email(&req.email) {
+            Ok(Some((user, permission_names, orphans))) => {
+                let response = GetUserResponse {
+                    success: true,
+                    message: "User found".to_string(),
+                    user: Some(crate::iam::User {
+                        id: user.id,
+                        name: user.name,
+                        email: user.email,
+                        permissions: permission_names,
+                    }),
+                    has_orphan_bits: orphans != 0,
+                    orphan_bit_values: split_bits(orphans),
+                };
+                Ok(Response::new(response))
+            }
+            Ok(None) => {
+                if self.status_codes {
+                    return Err(Status::not_found("User not found"));
+                }
+                let response = GetUserResponse {
+                    success: false,
+                    message: "User not found".to_string(),
+                    user: None,
+                    has_orphan_bits: false,
+                    orphan_bit_values: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = GetUserResponse {
+                    success: false,
+                    message: e.to_string(),
+                    user: None,
+                    has_orphan_bits: false,
+                    orphan_bit_values: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn update_user(
+        &self,
+        request: Request<UpdateUserRequest>,
+    ) -> Result<Response<UpdateUserResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.update_user(&req.user_id, req.name, req.email, req.password) {
+            Ok(user) => {
+                let permission_names = self.permission_names_for(user.effective_permissions(unix_timestamp())).unwrap_or_default();
+
+                let response = UpdateUserResponse {
+                    success: true,
+                    message: "User updated successfully".to_string(),
+                    user: Some(crate::iam::User {
+                        id: user.id,
+                        name: user.name,
+                        email: user.email,
+                        permissions: permission_names,
+                    }),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = UpdateUserResponse {
+                    success: false,
+                    message: e.to_string(),
+                    user: None,
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn delete_user(
+        &self,
+        request: Request<DeleteUserRequest>,
+    ) -> Result<Response<DeleteUserResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.delete_user(&req.user_id) {
+            Ok(()) => {
+                let response = DeleteUserResponse {
+                    success: true,
+                    message: "User deleted successfully".to_string(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = DeleteUserResponse {
+                    success: false,
+                    message: e.to_string(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn list_users(
+        &self,
+        request: Request<ListUsersRequest>,
+    ) -> Result<Response<ListUsersResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.list_users(req.page_size as usize, &req.page_token) {
+            Ok((users, next_page_token)) => {
+                let response = ListUsersResponse {
+                    success: true,
+                    message: format!("{} users listed", users.len()),
+                    users: users.into_iter().map(|(user, permission_names)| crate::iam::User {
+                        id: user.id,
+                        name: user.name,
+                        email: user.email,
+                        permissions: permission_names,
+                    }).collect(),
+                    next_page_token,
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = ListUsersResponse {
+                    success: false,
+                    message: e.to_string(),
+                    users: Vec::new(),
+                    next_page_token: String::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    type StreamUsersStream = tokio_stream::Iter<UserMessages>;
+
+    // One message per user, read STREAM_CHUNK_SIZE users at a time as the client consumes the stream
+    async fn stream_users(
+        &self,
+        _request: Request<StreamUsersRequest>,
+    ) -> Result<Response<Self::StreamUsersStream>, Status> {
+        self.ensure_available()?;
+        let messages = UserMessages(self.user_chunks(STREAM_CHUNK_SIZE));
+
+        Ok(Response::new(tokio_stream::iter(messages)))
+    }
+
+    async fn verify_credentials(
+        &self,
+        request: Request<VerifyCredentialsRequest>,
+    ) -> Result<Response<VerifyCredentialsResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.verify_credentials(&req.email, &req.password) {
+            Ok((user, permission_names)) => {
+                let response = VerifyCredentialsResponse {
+                    success: true,
+                    message: "Credentials verified".to_string(),
+                    user: Some(crate::iam::User {
+                        id: user.id,
+                        name: user.name,
+                        email: user.email,
+                        permissions: permission_names,
+                    }),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = VerifyCredentialsResponse {
+                    success: false,
+                    message: e.to_string(),
+                    user: None,
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn add_permission(
+        &self,
+        request: Request<AddPermissionRequest>,
+    ) -> Result<Response<AddPermissionResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.add_permission(req.permission_name, req.label, req.description, req.max_holders, req.requested_value) {
+            Ok(permission) => {
+                let response = AddPermissionResponse {
+                    success: true,
+                    message: "Permission added successfully".to_string(),
+                    permission: Some(permission.into()),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = AddPermissionResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permission: None,
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn add_composite_permission(
+        &self,
+        request: Request<AddCompositePermissionRequest>,
+    ) -> Result<Response<AddCompositePermissionResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.add_composite_permission(req.permission_name, req.members, req.label, req.description) {
+            Ok(permission) => {
+                let response = AddCompositePermissionResponse {
+                    success: true,
+                    message: "Composite permission added successfully".to_string(),
+                    permission: Some(permission.into()),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = AddCompositePermissionResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permission: None,
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn remove_permission(
+        &self,
+        request: Request<RemovePermissionRequest>,
+    ) -> Result<Response<RemovePermissionResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.remove_permission(&req.permission_name) {
+            Ok(()) => {
+                let response = RemovePermissionResponse {
+                    success: true,
+                    message: "Permission removed successfully".to_string(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = RemovePermissionResponse {
+                    success: false,
+                    message: e.to_string(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn list_permissions(
+        &self,
+        _request: Request<ListPermissionsRequest>,
+    ) -> Result<Response<ListPermissionsResponse>, Status> {
+        match self.list_permissions() {
+            Ok(permissions) => {
+                let response = ListPermissionsResponse {
+                    success: true,
+                    message: "Permissions listed successfully".to_string(),
+                    permissions: permissions.into_iter().map(crate::iam::Permission::from).collect(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = ListPermissionsResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permissions: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn export_permissions(
+        &self,
+        _request: Request<ExportPermissionsRequest>,
+    ) -> Result<Response<ExportPermissionsResponse>, Status> {
+        match self.export_permissions() {
+            Ok(permissions) => {
+                let response = ExportPermissionsResponse {
+                    success: true,
+                    message: format!("{} permissions exported", permissions.len()),
+                    permissions: permissions.into_iter().map(crate::iam::Permission::from).collect(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = ExportPermissionsResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permissions: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn import_permissions(
+        &self,
+        request: Request<ImportPermissionsRequest>,
+    ) -> Result<Response<ImportPermissionsResponse>, Status> {
+        let req = request.into_inner();
+
+        let incoming: Vec<Permission> = req.permissions.into_iter().map(Permission::from).collect();
+
+        match self.import_permissions(incoming) {
+            Ok((imported, skipped)) => {
+                let response = ImportPermissionsResponse {
+                    success: true,
+                    message: format!("{} permissions imported, {} already present", imported.len(), skipped.len()),
+                    imported: imported.into_iter().map(crate::iam::Permission::from).collect(),
+                    skipped,
+                    conflicts: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                let message = format!("Import rejected: {}", e);
+                if self.status_codes {
+                    return Err(Status::new(e.code(), message));
+                }
+                let response = ImportPermissionsResponse {
+                    success: false,
+                    message,
+                    imported: Vec::new(),
+                    skipped: Vec::new(),
+                    conflicts: e.details(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn get_permission_by_value(
+        &self,
+        request: Request<GetPermissionByValueRequest>,
+    ) -> Result<Response<GetPermissionByValueResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.get_permission_by_value(req.value) {
+            Ok(permission) => {
+                let response = GetPermissionByValueResponse {
+                    success: true,
+                    message: "Permission found".to_string(),
+                    permission: Some(permission.into()),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = GetPermissionByValueResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permission: None,
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn search_permissions(
+        &self,
+        request: Request<SearchPermissionsRequest>,
+    ) -> Result<Response<SearchPermissionsResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.search_permissions(&req.prefix, req.limit as usize) {
+            Ok(permissions) => {
+                let response = SearchPermissionsResponse {
+                    success: true,
+                    message: format!("{} permissions match '{}'", permissions.len(), req.prefix),
+                    permissions: permissions.into_iter().map(crate::iam::Permission::from).collect(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = SearchPermissionsResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permissions: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn describe_permission(
+        &self,
+        request: Request<DescribePermissionRequest>,
+    ) -> Result<Response<DescribePermissionResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.describe_permission(&req.permission_name) {
+            Ok((permission, holders, composites, roles)) => {
+                let response = DescribePermissionResponse {
+                    success: true,
+                    message: format!("Permission held by {} users", holders),
+                    permission: Some(permission.into()),
+                    holder_count: holders as u32,
+                    composites,
+                    roles,
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = DescribePermissionResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permission: None,
+                    holder_count: 0,
+                    composites: Vec::new(),
+                    roles: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn set_permission_label(
+        &self,
+        request: Request<SetPermissionLabelRequest>,
+    ) -> Result<Response<SetPermissionLabelResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.set_permission_label(&req.permission_name, req.label) {
+            Ok(permission) => {
+                let response = SetPermissionLabelResponse {
+                    success: true,
+                    message: "Permission label updated successfully".to_string(),
+                    permission: Some(permission.into()),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = SetPermissionLabelResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permission: None,
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn rename_permission(
+        &self,
+        request: Request<RenamePermissionRequest>,
+    ) -> Result<Response<RenamePermissionResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.rename_permission(&req.old_name, &req.new_name) {
+            Ok(permission) => {
+                let response = RenamePermissionResponse {
+                    success: true,
+                    message: "Permission renamed successfully".to_string(),
+                    permission: Some(permission.into()),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = RenamePermissionResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permission: None,
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn batch_rename_permissions(
+        &self,
+        request: Request<BatchRenamePermissionsRequest>,
+    ) -> Result<Response<BatchRenamePermissionsResponse>, Status> {
+        let req = request.into_inner();
+
+        let renames: Vec<(String, String)> = req.renames.into_iter()
+            .map(|r| (r.old_name, r.new_name))
+            .collect();
+
+        match self.batch_rename_permissions(&renames) {
+            Ok(renamed) => {
+                let response = BatchRenamePermissionsResponse {
+                    success: true,
+                    message: format!("{} permissions renamed", renamed.len()),
+                    renamed: renamed.into_iter().map(crate::iam::Permission::from).collect(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = BatchRenamePermissionsResponse {
+                    success: false,
+                    message: e.to_string(),
+                    renamed: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn apply_permission_template(
+        &self,
+        request: Request<ApplyPermissionTemplateRequest>,
+    ) -> Result<Response<ApplyPermissionTemplateResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.apply_permission_template(&req.template_name) {
+            Ok((added, skipped)) => {
+                let response = ApplyPermissionTemplateResponse {
+                    success: true,
+                    message: format!("Template applied: {} added, {} already present", added.len(), skipped.len()),
+                    added: added.into_iter().map(crate::iam::Permission::from).collect(),
+                    skipped,
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = ApplyPermissionTemplateResponse {
+                    success: false,
+                    message: e.to_string(),
+                    added: Vec::new(),
+                    skipped: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn reserve_bits(
+        &self,
+        request: Request<ReserveBitsRequest>,
+    ) -> Result<Response<ReserveBitsResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.reserve_bits(&req.values) {
+            Ok(reserved_values) => {
+                let response = ReserveBitsResponse {
+                    success: true,
+                    message: "Bits reserved successfully".to_string(),
+                    reserved_values,
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = ReserveBitsResponse {
+                    success: false,
+                    message: e.to_string(),
+                    reserved_values: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn update_user_permissions(
+        &self,
+        request: Request<UpdateUserPermissionsRequest>,
+    ) -> Result<Response<UpdateUserPermissionsResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.update_user_permissions(&req.user_id, req.permissions) {
+            Ok((user, added_permissions, removed_permissions)) => {
+                let permission_names = self.permission_names_for(user.effective_permissions(unix_timestamp())).unwrap_or_default();
+
+                let response = UpdateUserPermissionsResponse {
+                    success: true,
+                    message: "User permissions updated successfully".to_string(),
+                    user: Some(crate::iam::User {
+                        id: user.id,
+                        name: user.name,
+                        email: user.email,
+                        permissions: permission_names,
+                    }),
+                    added_permissions,
+                    removed_permissions,
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = UpdateUserPermissionsResponse {
+                    success: false,
+                    message: e.to_string(),
+                    user: None,
+                    added_permissions: Vec::new(),
+                    removed_permissions: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn grant_permission(
+        &self,
+        request: Request<GrantPermissionRequest>,
+    ) -> Result<Response<GrantPermissionResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.grant_permission(&req.user_id, &req.permission_name) {
+            Ok(permissions) => {
+                let response = GrantPermissionResponse {
+                    success: true,
+                    message: "Permission granted successfully".to_string(),
+                    permissions,
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = GrantPermissionResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permissions: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn grant_temporary_permission(
+        &self,
+        request: Request<GrantTemporaryPermissionRequest>,
+    ) -> Result<Response<GrantTemporaryPermissionResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.grant_temporary_permission(&req.user_id, &req.permission_name, Duration::from_secs(req.duration_seconds)) {
+            Ok((permissions, expires_at)) => {
+                let response = GrantTemporaryPermissionResponse {
+                    success: true,
+                    message: "Temporary permission granted successfully".to_string(),
+                    permissions,
+                    expires_at,
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = GrantTemporaryPermissionResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permissions: Vec::new(),
+                    expires_at: 0,
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn revoke_permission(
+        &self,
+        request: Request<RevokePermissionRequest>,
+    ) -> Result<Response<RevokePermissionResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.revoke_permission(&req.user_id, &req.permission_name) {
+            Ok(permissions) => {
+                let response = RevokePermissionResponse {
+                    success: true,
+                    message: "Permission revoked successfully".to_string(),
+                    permissions,
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = RevokePermissionResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permissions: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
         }
     }
 
-    pub fn add_permission(&self, name: String) -> Result<Permission, String> {
-        let mut permissions = self.permissions.write().map_err(|_| "Failed to acquire write lock")?;
+    async fn reset_user_permissions(
+        &self,
+        request: Request<ResetUserPermissionsRequest>,
+    ) -> Result<Response<ResetUserPermissionsResponse>, Status> {
+        let req = request.into_inner();
 
-        if permissions.contains_key(&name) {
-            return Err("Permission already exists".to_string());
+        match self.reset_user_permissions(&req.user_id) {
+            Ok(permissions) => {
+                let response = ResetUserPermissionsResponse {
+                    success: true,
+                    message: "User permissions reset successfully".to_string(),
+                    permissions,
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = ResetUserPermissionsResponse {
+                    success: false,
+                    message: e.to_string(),
+                    permissions: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
         }
-
-        let used_values = permissions.values().map(|p| p.value).collect::<Vec<_>>();
-        let next_value = find_next_available_bit(&used_values)?;
-
-        let permission = Permission::new(name.clone(), next_value);
-
-        permissions.insert(name, permission.clone());
-        Ok(permission)
     }
 
-    pub fn remove_permission(&self, name: &str) -> Result<(), String> {
-        // TODO: Peut entrainer incohérence lors de nouvelles créations de permissions -> Supprimer la permission sur les users concernés
-        let mut permissions = self.permissions.write().map_err(|_| "Failed to acquire write lock")?;
+    async fn batch_grant_permissions(
+        &self,
+        request: Request<BatchGrantPermissionsRequest>,
+    ) -> Result<Response<BatchGrantPermissionsResponse>, Status> {
+        let req = request.into_inner();
 
-        if permissions.remove(name).is_some() {
-            Ok(())
-        } else {
-            Err("Permission not found".to_string())
+        match self.batch_grant_permissions(&req.user_ids, &req.permissions) {
+            Ok(results) => {
+                let granted = results.iter().filter(|(_, result)| result.is_ok()).count();
+                let response = BatchGrantPermissionsResponse {
+                    success: true,
+                    message: format!("Permissions granted to {} of {} users", granted, results.len()),
+                    results: results.into_iter().map(|(user_id, result)| match result {
+                        Ok(permissions) => BatchGrantResult {
+                            user_id,
+                            success: true,
+                            message: "Permissions granted successfully".to_string(),
+                            permissions,
+                        },
+                        Err(e) => BatchGrantResult {
+                            user_id,
+                            success: false,
+                            message: e.to_string(),
+                            permissions: Vec::new(),
+                        },
+                    }).collect(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = BatchGrantPermissionsResponse {
+                    success: false,
+                    message: e.to_string(),
+                    results: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
         }
     }
 
-    pub fn list_permissions(&self) -> Result<Vec<Permission>, String> {
-        let permissions = self.permissions.read().map_err(|_| "Failed to acquire read lock")?;
-        let mut perms: Vec<Permission> = permissions.values().cloned().collect();
-        perms.sort_by(|a, b| a.value.cmp(&b.value));
-        Ok(perms)
-    }
-
-    pub fn check_permissions(&self, user_id: &str, required_permission_names: &[String]) -> Result<(bool, Vec<String>), String> {
-        // Exemple complet
-        // Utilisateur avec READ(1) + WRITE(2) = 3
-        // Demande READ(1) + DELETE(8) = 9
-        // required_bits = 9           // 1001
-        // user.permissions = 3        // 0011
-        // has_all = (3 & 9) == 9     // (0011 & 1001) == 1001 → 1 == 9 → false
-        // missing_bits = 9 & !3      // 1001 & 1100 = 1000 (DELETE)
-
-        // Résultat: (false, ["DELETE"])
-        let users = self.users.read().map_err(|_| "Failed to acquire read lock")?;
-
-        if let Some(user) = users.get(user_id) {
-            let permissions = self.permissions.read().map_err(|_| "Failed to acquire read lock")?;
-            let required_bits = permission_names_to_bits(&permissions, required_permission_names)?;
-            let has_all = (user.permissions & required_bits) == required_bits;
-
-            // Find missing permissions
-            let missing_bits = required_bits & !user.permissions;
-            // ! = not bitwise pour inverser les bits
-            // Exemple :
-            // required_bits = 11          // 1011 (READ|WRITE|DELETE)
-            // user.permissions = 3        // 0011 (READ|WRITE)
+    async fn create_role(
+        &self,
+        request: Request<CreateRoleRequest>,
+    ) -> Result<Response<CreateRoleResponse>, Status> {
+        let req = request.into_inner();
 
-            // !user.permissions = ~3      // 1100 (tous les bits sauf read/write)
-            // missing_bits = 11 & ~3 = 8  // 1011 & 1100 = 1000 (DELETE manquant)
-            let missing_permissions = if missing_bits == 0 {
-                Vec::new()
-            } else {
-                bits_to_permission_names(&permissions, missing_bits)?
-            };
+        match self.create_role(req.role_name, &req.permissions) {
+            Ok(role) => {
+                let permission_names = self.permission_names_for(role.bits).unwrap_or_default();
 
-            Ok((has_all, missing_permissions))
-        } else {
-            Err("User not found".to_string())
+                let response = CreateRoleResponse {
+                    success: true,
+                    message: "Role created successfully".to_string(),
+                    role: Some(crate::iam::Role {
+                        name: role.name,
+                        permissions: permission_names,
+                    }),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = CreateRoleResponse {
+                    success: false,
+                    message: e.to_string(),
+                    role: None,
+                };
+                Ok(Response::new(response))
+            }
         }
     }
 
-}
+    async fn list_roles(
+        &self,
+        _request: Request<ListRolesRequest>,
+    ) -> Result<Response<ListRolesResponse>, Status> {
+        match self.list_roles() {
+            Ok(roles) => {
+                let response = ListRolesResponse {
+                    success: true,
+                    message: "Roles listed successfully".to_string(),
+                    roles: roles.into_iter().map(|(role, permission_names)| crate::iam::Role {
+                        name: role.name,
+                        permissions: permission_names,
+                    }).collect(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = ListRolesResponse {
+                    success: false,
+                    message: e.to_string(),
+                    roles: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
 
-#[tonic::async_trait]
-impl IamService for IamManager {
-    async fn create_user(
+    async fn assign_role(
         &self,
-        request: Request<CreateUserRequest>,
-    ) -> Result<Response<CreateUserResponse>, Status> {
+        request: Request<AssignRoleRequest>,
+    ) -> Result<Response<AssignRoleResponse>, Status> {
         let req = request.into_inner();
 
-        match self.create_user(req.name, req.email, req.password, req.permissions) {
+        match self.assign_role(&req.user_id, &req.role_name) {
             Ok(user) => {
-                let permissions = self.permissions.read().unwrap_or_else(|_| panic!("Lock poisoned"));
-                let permission_names = bits_to_permission_names(&permissions, user.permissions)
-                    .unwrap_or_else(|_| Vec::new());
+                let permission_names = self.permission_names_for(user.effective_permissions(unix_timestamp())).unwrap_or_default();
 
-                let response = CreateUserResponse {
+                let response = AssignRoleResponse {
                     success: true,
-                    message: "User created successfully".to_string(),
+                    message: "Role assigned successfully".to_string(),
                     user: Some(crate::iam::User {
                         id: user.id,
                         name: user.name,
@@ -176,9 +2759,12 @@ impl IamService for IamManager {
                 Ok(Response::new(response))
             }
             Err(e) => {
-                let response = CreateUserResponse {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = AssignRoleResponse {
                     success: false,
-                    message: e,
+                    message: e.to_string(),
                     user: None,
                 };
                 Ok(Response::new(response))
@@ -186,191 +2772,377 @@ impl IamService for IamManager {
         }
     }
 
-    async fn get_user(
+    async fn check_permissions(
         &self,
-        request: Request<GetUserRequest>,
-    ) -> Result<Response<GetUserResponse>, Status> {
+        request: Request<CheckPermissionsRequest>,
+    ) -> Result<Response<CheckPermissionsResponse>, Status> {
         let req = request.into_inner();
 
-        match self.get_user_with_permission_names(&req.user_id) {
-            Ok(Some((user, permission_names))) => {
-                let response = GetUserResponse {
+        let checked = match_mode(req.mode).and_then(|mode| {
+            self.check_permissions(&req.user_id, &req.required_permissions, mode)
+                .map(|(has_permissions, missing_permissions)| (mode, has_permissions, missing_permissions))
+        });
+
+        match &checked {
+            Ok((_, has_permissions, missing_permissions)) => self.metrics.record(*has_permissions, missing_permissions),
+            Err(_) => self.metrics.record_failure(),
+        }
+
+        match checked {
+            Ok((mode, has_permissions, missing_permissions)) => {
+                let message = check_permissions_message(has_permissions, mode, &missing_permissions);
+
+                let response = CheckPermissionsResponse {
                     success: true,
-                    message: "User found".to_string(),
-                    user: Some(crate::iam::User {
-                        id: user.id,
-                        name: user.name,
-                        email: user.email,
-                        permissions: permission_names,
-                    }),
+                    has_permissions,
+                    message,
+                    missing_permissions,
                 };
                 Ok(Response::new(response))
             }
-            Ok(None) => {
-                let response = GetUserResponse {
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = CheckPermissionsResponse {
                     success: false,
-                    message: "User not found".to_string(),
-                    user: None,
+                    has_permissions: false,
+                    message: e.to_string(),
+                    missing_permissions: Vec::new(),
+                };
+                Ok(Response::new(response))
+            }
+        }
+    }
+
+    async fn check_permissions_by_mask(
+        &self,
+        request: Request<CheckPermissionsByMaskRequest>,
+    ) -> Result<Response<CheckPermissionsByMaskResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.check_permissions_by_mask(&req.user_id, req.required_mask) {
+            Ok((has_permissions, missing_mask)) => {
+                let missing_permissions = self.permission_names_for(missing_mask).unwrap_or_default();
+                self.metrics.record(has_permissions, &missing_permissions);
+
+                let message = if has_permissions {
+                    "User has all required permissions".to_string()
+                } else {
+                    format!("User is missing bits {:?}", split_bits(missing_mask))
+                };
+
+                let response = CheckPermissionsByMaskResponse {
+                    success: true,
+                    has_permissions,
+                    message,
+                    missing_mask,
                 };
                 Ok(Response::new(response))
             }
             Err(e) => {
-                let response = GetUserResponse {
+                self.metrics.record_failure();
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = CheckPermissionsByMaskResponse {
                     success: false,
-                    message: e,
-                    user: None,
+                    has_permissions: false,
+                    message: e.to_string(),
+                    missing_mask: 0,
                 };
                 Ok(Response::new(response))
             }
         }
     }
 
-    async fn add_permission(
+    async fn get_user_permission_mask(
         &self,
-        request: Request<AddPermissionRequest>,
-    ) -> Result<Response<AddPermissionResponse>, Status> {
+        request: Request<GetUserPermissionMaskRequest>,
+    ) -> Result<Response<GetUserPermissionMaskResponse>, Status> {
         let req = request.into_inner();
 
-        match self.add_permission(req.permission_name) {
-            Ok(permission) => {
-                let response = AddPermissionResponse {
+        match self.get_user_permission_mask(&req.user_id) {
+            Ok((permission_mask, registry_mask)) => {
+                let response = GetUserPermissionMaskResponse {
                     success: true,
-                    message: "Permission added successfully".to_string(),
-                    permission: Some(crate::iam::Permission {
-                        name: permission.name,
-                        value: permission.value,
-                    }),
+                    message: "User permission mask retrieved".to_string(),
+                    permission_mask,
+                    registry_mask,
                 };
                 Ok(Response::new(response))
             }
             Err(e) => {
-                let response = AddPermissionResponse {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = GetUserPermissionMaskResponse {
                     success: false,
-                    message: e,
-                    permission: None,
+                    message: e.to_string(),
+                    permission_mask: 0,
+                    registry_mask: 0,
                 };
                 Ok(Response::new(response))
             }
         }
     }
 
-    async fn remove_permission(
+    async fn batch_check_permissions(
         &self,
-        request: Request<RemovePermissionRequest>,
-    ) -> Result<Response<RemovePermissionResponse>, Status> {
+        request: Request<BatchCheckPermissionsRequest>,
+    ) -> Result<Response<BatchCheckPermissionsResponse>, Status> {
         let req = request.into_inner();
 
-        match self.remove_permission(&req.permission_name) {
-            Ok(()) => {
-                let response = RemovePermissionResponse {
+        // Each entry runs the single-user check, an unknown user or permission fails that entry only
+        let mut results = Vec::new();
+        for entry in req.entries {
+            let result = match match_mode(entry.mode) {
+                Ok(mode) => match self.check_permissions(&entry.user_id, &entry.required_permissions, mode) {
+                    Ok((has_permissions, missing_permissions)) => {
+                        self.metrics.record(has_permissions, &missing_permissions);
+                        BatchCheckResult {
+                            user_id: entry.user_id,
+                            success: true,
+                            has_permissions,
+                            message: check_permissions_message(has_permissions, mode, &missing_permissions),
+                            missing_permissions,
+                        }
+                    }
+                    Err(e) => BatchCheckResult {
+                        user_id: entry.user_id,
+                        success: false,
+                        has_permissions: false,
+                        message: e.to_string(),
+                        missing_permissions: Vec::new(),
+                    },
+                },
+                Err(e) => BatchCheckResult {
+                    user_id: entry.user_id,
+                    success: false,
+                    has_permissions: false,
+                    message: e.to_string(),
+                    missing_permissions: Vec::new(),
+                },
+            };
+            if !result.success {
+                self.metrics.record_failure();
+            }
+            results.push(result);
+        }
+
+        let checked = results.iter().filter(|r| r.success).count();
+        let response = BatchCheckPermissionsResponse {
+            success: true,
+            message: format!("{} of {} checks completed", checked, results.len()),
+            results,
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn get_users_missing_permission(
+        &self,
+        request: Request<GetUsersMissingPermissionRequest>,
+    ) -> Result<Response<GetUsersMissingPermissionResponse>, Status> {
+        let req = request.into_inner();
+
+        match self.get_users_missing_permission(&req.permission_name) {
+            Ok(users) => {
+                let response = GetUsersMissingPermissionResponse {
                     success: true,
-                    message: "Permission removed successfully".to_string(),
+                    message: format!("{} users missing permission {}", users.len(), req.permission_name),
+                    users: users.into_iter().map(|(user, permission_names)| crate::iam::User {
+                        id: user.id,
+                        name: user.name,
+                        email: user.email,
+                        permissions: permission_names,
+                    }).collect(),
                 };
                 Ok(Response::new(response))
             }
             Err(e) => {
-                let response = RemovePermissionResponse {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = GetUsersMissingPermissionResponse {
                     success: false,
-                    message: e,
+                    message: e.to_string(),
+                    users: Vec::new(),
                 };
                 Ok(Response::new(response))
             }
         }
     }
 
-    async fn list_permissions(
+    async fn get_metrics(
         &self,
-        _request: Request<ListPermissionsRequest>,
-    ) -> Result<Response<ListPermissionsResponse>, Status> {
-        match self.list_permissions() {
-            Ok(permissions) => {
-                let response = ListPermissionsResponse {
+        _request: Request<GetMetricsRequest>,
+    ) -> Result<Response<GetMetricsResponse>, Status> {
+        let metrics = self.metrics();
+        let response = GetMetricsResponse {
+            success: true,
+            message: format!("{} permission checks recorded", metrics.checks),
+            checks: metrics.checks,
+            allowed: metrics.allowed,
+            denied: metrics.denied,
+            denials: metrics.denials.into_iter()
+                .map(|(permission_name, count)| PermissionDenialCount { permission_name, count })
+                .collect(),
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn check_integrity(
+        &self,
+        _request: Request<CheckIntegrityRequest>,
+    ) -> Result<Response<CheckIntegrityResponse>, Status> {
+        match self.check_integrity() {
+            Ok(violations) => {
+                let message = if violations.is_empty() {
+                    "No integrity violations found".to_string()
+                } else {
+                    format!("{} integrity violations found", violations.len())
+                };
+
+                let response = CheckIntegrityResponse {
                     success: true,
-                    message: "Permissions listed successfully".to_string(),
-                    permissions: permissions.into_iter().map(|p| crate::iam::Permission {
-                        name: p.name,
-                        value: p.value,
+                    message,
+                    healthy: violations.is_empty(),
+                    violations: violations.into_iter().map(|v| crate::iam::IntegrityViolation {
+                        kind: v.kind.as_str().to_string(),
+                        subject: v.subject,
+                        detail: v.detail,
                     }).collect(),
                 };
                 Ok(Response::new(response))
             }
             Err(e) => {
-                let response = ListPermissionsResponse {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = CheckIntegrityResponse {
                     success: false,
-                    message: e,
-                    permissions: Vec::new(),
+                    message: e.to_string(),
+                    healthy: false,
+                    violations: Vec::new(),
                 };
                 Ok(Response::new(response))
             }
         }
     }
 
-    async fn update_user_permissions(
+    async fn describe_user(
         &self,
-        request: Request<UpdateUserPermissionsRequest>,
-    ) -> Result<Response<UpdateUserPermissionsResponse>, Status> {
+        request: Request<DescribeUserRequest>,
+    ) -> Result<Response<DescribeUserResponse>, Status> {
         let req = request.into_inner();
 
-        match self.update_user_permissions(&req.user_id, req.permissions) {
-            Ok(user) => {
-                let permissions = self.permissions.read().unwrap_or_else(|_| panic!("Lock poisoned"));
-                let permission_names = bits_to_permission_names(&permissions, user.permissions)
-                    .unwrap_or_else(|_| Vec::new());
-
-                let response = UpdateUserPermissionsResponse {
+        match self.describe_user(&req.user_id) {
+            Ok((user, permission_names, password_cost)) => {
+                let response = DescribeUserResponse {
                     success: true,
-                    message: "User permissions updated successfully".to_string(),
+                    message: "User found".to_string(),
                     user: Some(crate::iam::User {
                         id: user.id,
                         name: user.name,
                         email: user.email,
                         permissions: permission_names,
                     }),
+                    password_cost,
                 };
                 Ok(Response::new(response))
             }
             Err(e) => {
-                let response = UpdateUserPermissionsResponse {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = DescribeUserResponse {
                     success: false,
-                    message: e,
+                    message: e.to_string(),
                     user: None,
+                    password_cost: 0,
                 };
                 Ok(Response::new(response))
             }
         }
     }
 
-    async fn check_permissions(
+    async fn export_user(
         &self,
-        request: Request<CheckPermissionsRequest>,
-    ) -> Result<Response<CheckPermissionsResponse>, Status> {
+        request: Request<ExportUserRequest>,
+    ) -> Result<Response<ExportUserResponse>, Status> {
         let req = request.into_inner();
 
-        match self.check_permissions(&req.user_id, &req.required_permissions) {
-            Ok((has_permissions, missing_permissions)) => {
-                let message = if has_permissions {
-                    "User has all required permissions".to_string()
-                } else {
-                    format!("User is missing permissions: {}", missing_permissions.join(", "))
+        match self.export_user(&req.user_id, req.include_audit) {
+            Ok((user, permission_names, roles, audit_entries)) => {
+                let response = ExportUserResponse {
+                    success: true,
+                    message: "User exported".to_string(),
+                    user: Some(crate::iam::User {
+                        id: user.id,
+                        name: user.name,
+                        email: user.email,
+                        permissions: permission_names,
+                    }),
+                    roles,
+                    audit_entries: audit_entries.into_iter().map(|(entry, before, after)| crate::iam::AuditEntry {
+                        timestamp: entry.timestamp,
+                        action: entry.action.as_str().to_string(),
+                        user_id: entry.user_id,
+                        before_permissions: before,
+                        after_permissions: after,
+                    }).collect(),
+                };
+                Ok(Response::new(response))
+            }
+            Err(e) => {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = ExportUserResponse {
+                    success: false,
+                    message: e.to_string(),
+                    user: None,
+                    roles: Vec::new(),
+                    audit_entries: Vec::new(),
                 };
+                Ok(Response::new(response))
+            }
+        }
+    }
 
-                let response = CheckPermissionsResponse {
+    async fn get_audit_log(
+        &self,
+        request: Request<GetAuditLogRequest>,
+    ) -> Result<Response<GetAuditLogResponse>, Status> {
+        let req = request.into_inner();
+
+        let user_id = if req.user_id.is_empty() { None } else { Some(req.user_id.as_str()) };
+        match self.get_audit_log(user_id, req.limit as usize) {
+            Ok(entries) => {
+                let response = GetAuditLogResponse {
                     success: true,
-                    has_permissions,
-                    message,
-                    missing_permissions,
+                    message: format!("{} audit entries", entries.len()),
+                    entries: entries.into_iter().map(|(entry, before, after)| crate::iam::AuditEntry {
+                        timestamp: entry.timestamp,
+                        action: entry.action.as_str().to_string(),
+                        user_id: entry.user_id,
+                        before_permissions: before,
+                        after_permissions: after,
+                    }).collect(),
                 };
                 Ok(Response::new(response))
             }
             Err(e) => {
-                let response = CheckPermissionsResponse {
+                if self.status_codes {
+                    return Err(e.into());
+                }
+                let response = GetAuditLogResponse {
                     success: false,
-                    has_permissions: false,
-                    message: e,
-                    missing_permissions: Vec::new(),
+                    message: e.to_string(),
+                    entries: Vec::new(),
                 };
                 Ok(Response::new(response))
             }
         }
     }
-}
\ No newline at end of file
+}
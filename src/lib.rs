@@ -1,11 +1,22 @@
+pub mod error;
 pub mod models;
 pub mod utils;
 pub mod iam_manager;
+pub mod logging;
+pub mod store;
+pub mod metrics;
+pub mod server;
 
 pub mod iam {
     tonic::include_proto!("iam");
+
+    #[cfg(feature = "reflection")]
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("iam_descriptor");
 }
 
-pub use models::{User, Permission};
-pub use utils::{permission_names_to_bits, bits_to_permission_names, find_next_available_bit};
-pub use iam_manager::IamManager;
\ No newline at end of file
+pub use error::IamError;
+pub use models::{User, NewUser, Permission, Role, PermissionRegistry, RegistryConflict, PasswordPolicy, SortOrder, MatchMode, IntegrityViolation, IntegrityViolationKind, AuditAction, AuditEntry};
+pub use utils::{normalize_permission_name, validate_permission_name, permission_names_to_bits, expand_permission_wildcards, bits_to_permission_names, bits_to_permission_names_with_order, compute_missing_bits, unix_timestamp, validate_email, find_next_available_bit, registry_mask, permissions_complement, orphan_bits, bcrypt_cost, validate_bcrypt_cost, split_bits, validate_permission_values, merge_registries, next_sequence};
+pub use store::{Store, StoreSnapshot, JsonFileStore};
+pub use metrics::{CheckMetrics, MetricsSnapshot};
+pub use iam_manager::{IamManager, UserChunks, BatchUserResult, UserWithPermissions, RenderedAuditEntry, PermissionUpdate, PermissionDescription, UserExport};
\ No newline at end of file
@@ -0,0 +1,159 @@
+use std::fmt::Debug;
+
+use prost::Message;
+
+use crate::iam::*;
+
+// Request payloads are logged through their redacted form so secrets never reach the output
+pub trait Redact: Debug + Clone {
+    fn redacted(&self) -> Self;
+}
+
+// Requests carrying no secret, logged as they are
+macro_rules! impl_redact_unchanged {
+    ($($request:ty),* $(,)?) => {
+        $(impl Redact for $request {
+            fn redacted(&self) -> Self {
+                self.clone()
+            }
+        })*
+    };
+}
+
+impl Redact for CreateUserRequest {
+    fn redacted(&self) -> Self {
+        Self {
+            password: "***".to_string(),
+            ..self.clone()
+        }
+    }
+}
+
+impl Redact for BatchCreateUsersRequest {
+    fn redacted(&self) -> Self {
+        Self {
+            users: self.users.iter().map(Redact::redacted).collect(),
+            ..self.clone()
+        }
+    }
+}
+
+impl Redact for VerifyCredentialsRequest {
+    fn redacted(&self) -> Self {
+        Self {
+            password: "***".to_string(),
+            ..self.clone()
+        }
+    }
+}
+
+impl Redact for UpdateUserRequest {
+    fn redacted(&self) -> Self {
+        Self {
+            password: self.password.as_ref().map(|_| "***".to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+impl_redact_unchanged!(
+    GetUserRequest,
+    GetUserByEmailRequest,
+    DeleteUserRequest,
+    ListUsersRequest,
+    StreamUsersRequest,
+    AddPermissionRequest,
+    AddCompositePermissionRequest,
+    RemovePermissionRequest,
+    ListPermissionsRequest,
+    ExportPermissionsRequest,
+    ImportPermissionsRequest,
+    GetPermissionByValueRequest,
+    SearchPermissionsRequest,
+    DescribePermissionRequest,
+    SetPermissionLabelRequest,
+    RenamePermissionRequest,
+    BatchRenamePermissionsRequest,
+    ApplyPermissionTemplateRequest,
+    ReserveBitsRequest,
+    UpdateUserPermissionsRequest,
+    GrantPermissionRequest,
+    GrantTemporaryPermissionRequest,
+    RevokePermissionRequest,
+    ResetUserPermissionsRequest,
+    BatchGrantPermissionsRequest,
+    CreateRoleRequest,
+    ListRolesRequest,
+    AssignRoleRequest,
+    CheckPermissionsRequest,
+    CheckPermissionsByMaskRequest,
+    GetUserPermissionMaskRequest,
+    BatchCheckPermissionsRequest,
+    GetUsersMissingPermissionRequest,
+    CheckIntegrityRequest,
+    GetAuditLogRequest,
+    GetMetricsRequest,
+    DescribeUserRequest,
+    ExportUserRequest,
+);
+
+pub fn format_request<T: Redact>(method: &str, payload: &T) -> String {
+    format!("{} request: {:?}", method, payload.redacted())
+}
+
+// Decodes an encoded IamService request message and formats it as format_request does, None for
+// an unknown method or a message that does not decode
+pub fn format_encoded_request(method: &str, message: &[u8]) -> Option<String> {
+    macro_rules! decode {
+        ($($method:literal => $request:ty),* $(,)?) => {
+            match method {
+                $($method => <$request>::decode(message).ok().map(|payload| format_request(method, &payload)),)*
+                _ => None,
+            }
+        };
+    }
+    decode! {
+        "CreateUser" => CreateUserRequest,
+        "BatchCreateUsers" => BatchCreateUsersRequest,
+        "VerifyCredentials" => VerifyCredentialsRequest,
+        "UpdateUser" => UpdateUserRequest,
+        "GetUser" => GetUserRequest,
+        "GetUserByEmail" => GetUserByEmailRequest,
+        "DeleteUser" => DeleteUserRequest,
+        "ListUsers" => ListUsersRequest,
+        "StreamUsers" => StreamUsersRequest,
+        "AddPermission" => AddPermissionRequest,
+        "AddCompositePermission" => AddCompositePermissionRequest,
+        "RemovePermission" => RemovePermissionRequest,
+        "ListPermissions" => ListPermissionsRequest,
+        "ExportPermissions" => ExportPermissionsRequest,
+        "ImportPermissions" => ImportPermissionsRequest,
+        "GetPermissionByValue" => GetPermissionByValueRequest,
+        "SearchPermissions" => SearchPermissionsRequest,
+        "DescribePermission" => DescribePermissionRequest,
+        "SetPermissionLabel" => SetPermissionLabelRequest,
+        "RenamePermission" => RenamePermissionRequest,
+        "BatchRenamePermissions" => BatchRenamePermissionsRequest,
+        "ApplyPermissionTemplate" => ApplyPermissionTemplateRequest,
+        "ReserveBits" => ReserveBitsRequest,
+        "UpdateUserPermissions" => UpdateUserPermissionsRequest,
+        "GrantPermission" => GrantPermissionRequest,
+        "GrantTemporaryPermission" => GrantTemporaryPermissionRequest,
+        "RevokePermission" => RevokePermissionRequest,
+        "ResetUserPermissions" => ResetUserPermissionsRequest,
+        "BatchGrantPermissions" => BatchGrantPermissionsRequest,
+        "CreateRole" => CreateRoleRequest,
+        "ListRoles" => ListRolesRequest,
+        "AssignRole" => AssignRoleRequest,
+        "CheckPermissions" => CheckPermissionsRequest,
+        "CheckPermissionsByMask" => CheckPermissionsByMaskRequest,
+        "GetUserPermissionMask" => GetUserPermissionMaskRequest,
+        "BatchCheckPermissions" => BatchCheckPermissionsRequest,
+        "GetUsersMissingPermission" => GetUsersMissingPermissionRequest,
+        "CheckIntegrity" => CheckIntegrityRequest,
+        "GetAuditLog" => GetAuditLogRequest,
+        "GetMetrics" => GetMetricsRequest,
+        "DescribeUser" => DescribeUserRequest,
+        "ExportUser" => ExportUserRequest,
+    }
+}
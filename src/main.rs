@@ -1,27 +1,110 @@
-use tonic::transport::Server;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::transport::{Server, ServerTlsConfig};
 
-mod models;
-mod utils;
-mod iam_manager;
+use bitflags_iam::iam::iam_service_server::IamServiceServer;
+use bitflags_iam::server::{self, health_service, ApiKeyInterceptor, RequestLogger};
+use bitflags_iam::{IamManager, JsonFileStore};
 
-pub mod iam {
-    tonic::include_proto!("iam");
+// Prints log records from the library (payload logging, integrity repairs) to stderr
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level().as_str().to_lowercase(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
 }
 
-use iam::iam_service_server::IamServiceServer;
-use iam_manager::IamManager;
+static LOGGER: StderrLogger = StderrLogger;
+
+// TLS identity from the IAM_TLS_CERT and IAM_TLS_KEY PEM files, None to serve plaintext
+fn tls_config() -> Result<Option<ServerTlsConfig>, Box<dyn std::error::Error>> {
+    match (std::env::var("IAM_TLS_CERT"), std::env::var("IAM_TLS_KEY")) {
+        (Ok(cert_path), Ok(key_path)) => Ok(Some(server::tls_config(&cert_path, &key_path)?)),
+        (Err(_), Err(_)) => Ok(None),
+        _ => Err("IAM_TLS_CERT and IAM_TLS_KEY must be set together".into()),
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr = "[::1]:50051".parse()?;
-    let iam_manager = IamManager::new();
+    let log_payloads = std::env::var("IAM_LOG_PAYLOADS").is_ok_and(|v| v == "1" || v == "true");
+    let status_codes = std::env::var("IAM_STATUS_CODES").is_ok_and(|v| v == "1" || v == "true");
+    log::set_logger(&LOGGER).map_err(|e| format!("Failed to install logger: {}", e))?;
+    log::set_max_level(if log_payloads { log::LevelFilter::Debug } else { log::LevelFilter::Info });
+    // Users, permissions and roles are kept in a JSON file when IAM_STORE_PATH is set.
+    // An unreadable store is left untouched: the service reports NOT_SERVING and rejects writes
+    // and permission checks with UNAVAILABLE instead of serving an empty registry.
+    let iam_manager = match std::env::var("IAM_STORE_PATH") {
+        Ok(path) if Path::new(&path).exists() => IamManager::load_from_path(path).unwrap_or_else(|e| {
+            eprintln!("Failed to load store: {}", e);
+            IamManager::unavailable(format!("Store failed to load: {}", e))
+        }),
+        Ok(path) => IamManager::new().with_store(JsonFileStore::new(path)),
+        Err(_) => IamManager::new(),
+    }
+    .with_status_codes(status_codes);
+    let iam_manager = Arc::new(iam_manager);
+
+    // IAM_API_KEYS="key=user_id,..." turns on API key checks for every RPC outside server::OPEN_RPCS.
+    // The users must hold IAM_ADMIN_PERMISSION (ADMIN by default, comma-separated for several).
+    let api_keys = std::env::var("IAM_API_KEYS").ok();
+    if let Some(api_keys) = &api_keys {
+        for entry in api_keys.split(',').filter(|entry| !entry.trim().is_empty()) {
+            let (key, user_id) = entry.split_once('=').ok_or_else(|| format!("Invalid IAM_API_KEYS entry '{}', expected key=user_id", entry))?;
+            iam_manager.add_api_key(key.trim().to_string(), user_id.trim())
+                .map_err(|e| format!("Failed to register API key for {}: {}", user_id.trim(), e))?;
+        }
+    }
+    let admin_permissions: Vec<String> = std::env::var("IAM_ADMIN_PERMISSION")
+        .unwrap_or_else(|_| "ADMIN".to_string())
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    // An empty requirement would let any valid key through
+    if admin_permissions.is_empty() {
+        return Err("IAM_ADMIN_PERMISSION must name at least one permission".into());
+    }
 
-    println!("IAM gRPC Server listening on {}", addr);
+    let health_service = health_service(&iam_manager).await;
 
-    Server::builder()
-        .add_service(IamServiceServer::new(iam_manager))
-        .serve(addr)
-        .await?;
+    // Periodic orphan bit repair, disabled unless an interval is given
+    if let Some(seconds) = std::env::var("IAM_REPAIR_INTERVAL_SECS").ok().and_then(|v| v.parse::<u64>().ok()).filter(|&s| s > 0) {
+        iam_manager.spawn_integrity_repair(Duration::from_secs(seconds));
+    }
+
+    let mut builder = Server::builder();
+    match tls_config()? {
+        Some(tls) => {
+            builder = builder.tls_config(tls)?;
+            println!("IAM gRPC Server listening on {} (TLS)", addr);
+        }
+        None => println!("IAM gRPC Server listening on {}", addr),
+    }
+
+    // Payloads are logged once debug records are on, that is with IAM_LOG_PAYLOADS
+    let iam_service = RequestLogger::new(IamServiceServer::from_arc(Arc::clone(&iam_manager)));
+    let router = builder.add_service(health_service);
+    let router = match api_keys {
+        Some(_) => router.add_service(ApiKeyInterceptor::new(iam_service, iam_manager, admin_permissions)),
+        None => router.add_service(iam_service),
+    };
+
+    #[cfg(feature = "reflection")]
+    let router = router.add_service(server::reflection_service()?);
+
+    router.serve(addr).await?;
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// Counters for permission checks, shared by every RPC handler
+#[derive(Debug, Default)]
+pub struct CheckMetrics {
+    checks: AtomicU64,
+    allowed: AtomicU64,
+    denied: AtomicU64,
+    // Denials per missing permission name
+    denials: Mutex<HashMap<String, u64>>,
+}
+
+// Point-in-time copy of the counters, denials sorted by name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub checks: u64,
+    pub allowed: u64,
+    pub denied: u64,
+    pub denials: Vec<(String, u64)>,
+}
+
+impl CheckMetrics {
+    // Counts a check that could not be evaluated (unknown user or permission)
+    pub fn record_failure(&self) {
+        self.checks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record(&self, has_permissions: bool, missing_permissions: &[String]) {
+        self.checks.fetch_add(1, Ordering::Relaxed);
+        if has_permissions {
+            self.allowed.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        self.denied.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut denials) = self.denials.lock() {
+            for name in missing_permissions {
+                *denials.entry(name.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let mut denials: Vec<(String, u64)> = self.denials.lock()
+            .map(|denials| denials.iter().map(|(name, count)| (name.clone(), *count)).collect())
+            .unwrap_or_default();
+        denials.sort();
+
+        MetricsSnapshot {
+            checks: self.checks.load(Ordering::Relaxed),
+            allowed: self.allowed.load(Ordering::Relaxed),
+            denied: self.denied.load(Ordering::Relaxed),
+            denials,
+        }
+    }
+}
@@ -1,19 +1,31 @@
+use std::collections::HashMap;
 use uuid::Uuid;
 use bcrypt::{hash, DEFAULT_COST};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+use crate::error::IamError;
+use crate::utils::{unix_timestamp, validate_bcrypt_cost};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
     pub name: String,
     pub email: String,
     pub password_hash: String,
     pub permissions: u64,
+    // Expiry (unix seconds) of temporarily granted bits, keyed by single bit value
+    #[serde(default)]
+    pub expiries: HashMap<u64, u64>,
 }
 
 impl User {
-    pub fn new(name: String, email: String, password: String, permissions: u64) -> Result<Self, String> {
-        let password_hash = hash(password, DEFAULT_COST)
-            .map_err(|e| format!("Failed to hash password: {}", e))?;
+    pub fn new(name: String, email: String, password: String, permissions: u64) -> Result<Self, IamError> {
+        Self::new_with_cost(name, email, password, permissions, DEFAULT_COST)
+    }
+
+    pub fn new_with_cost(name: String, email: String, password: String, permissions: u64, cost: u32) -> Result<Self, IamError> {
+        let password_hash = hash(password, validate_bcrypt_cost(cost)?)
+            .map_err(|e| IamError::Internal(format!("Failed to hash password: {}", e)))?;
 
         Ok(Self {
             id: Uuid::new_v4().to_string(),
@@ -21,18 +33,217 @@ impl User {
             email,
             password_hash,
             permissions,
+            expiries: HashMap::new(),
         })
     }
+
+    // Permissions minus the temporary grants that have expired at `now`
+    pub fn effective_permissions(&self, now: u64) -> u64 {
+        self.expiries.iter()
+            .filter(|(_, &expires_at)| expires_at <= now)
+            .fold(self.permissions, |bits, (bit, _)| bits & !bit)
+    }
+
+    // Makes the given bits permanent (or forgets them) by dropping their expiries
+    pub fn clear_expiries(&mut self, bits: u64) {
+        self.expiries.retain(|bit, _| bit & bits == 0);
+    }
 }
 
+// One entry of a batch user creation
 #[derive(Debug, Clone)]
+pub struct NewUser {
+    pub name: String,
+    pub email: String,
+    pub password: String,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Permission {
     pub name: String,
     pub value: u64,
+    // Human readable label for UIs, resolution always uses the canonical name
+    #[serde(default)]
+    pub label: String,
+    // Maximum number of users allowed to hold this permission at once
+    #[serde(default)]
+    pub max_holders: Option<u32>,
+    #[serde(default)]
+    pub description: String,
+    // Unix timestamp (seconds) of the registration
+    #[serde(default)]
+    pub created_at: u64,
+    // Named union of other permissions' bits rather than a bit of its own
+    #[serde(default)]
+    pub composite: bool,
+    // Registration order within its registry, the order SortOrder::Unsorted lists names in
+    #[serde(default)]
+    pub sequence: u64,
 }
 
 impl Permission {
     pub fn new(name: String, value: u64) -> Self {
-        Self { name, value }
+        Self { name, value, label: String::new(), max_holders: None, description: String::new(), created_at: unix_timestamp(), composite: false, sequence: 0 }
+    }
+
+    pub fn new_composite(name: String, value: u64, description: String) -> Self {
+        Self { composite: true, ..Self::new_with_description(name, value, description) }
+    }
+
+    pub fn new_with_description(name: String, value: u64, description: String) -> Self {
+        Self { description, ..Self::new(name, value) }
+    }
+}
+
+// Named bundle of permission bits, OR-ed into a user's permissions on assignment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Role {
+    pub name: String,
+    pub bits: u64,
+}
+
+impl Role {
+    pub fn new(name: String, bits: u64) -> Self {
+        Self { name, bits }
     }
-}
\ No newline at end of file
+}
+
+pub type PermissionRegistry = HashMap<String, Permission>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryConflict {
+    // Same name registered with different values
+    Value { name: String, left: u64, right: u64 },
+    // Same value registered under different names
+    Name { value: u64, left: String, right: String },
+}
+
+impl RegistryConflict {
+    pub fn detail(&self) -> String {
+        match self {
+            RegistryConflict::Value { name, left, right } =>
+                format!("Permission '{}' has conflicting values {} and {}", name, left, right),
+            RegistryConflict::Name { value, left, right } =>
+                format!("Value {} is used by both '{}' and '{}'", value, left, right),
+        }
+    }
+}
+
+// Rules a password must satisfy before it is hashed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_digit: bool,
+    pub require_uppercase: bool,
+    pub require_symbol: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self { min_length: 8, require_digit: false, require_uppercase: false, require_symbol: false }
+    }
+}
+
+impl PasswordPolicy {
+    // Reports every rule the password breaks at once
+    pub fn check(&self, password: &str) -> Result<(), IamError> {
+        let mut failures = Vec::new();
+        if password.chars().count() < self.min_length {
+            failures.push(format!("shorter than {} characters", self.min_length));
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            failures.push("no digit".to_string());
+        }
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            failures.push("no uppercase letter".to_string());
+        }
+        if self.require_symbol && password.chars().all(char::is_alphanumeric) {
+            failures.push("no symbol".to_string());
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(IamError::InvalidArgument(format!("Password too weak: {}", failures.join(", "))))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    #[default]
+    Alphabetical,
+    // Ascending value, ties broken by name
+    ByValue,
+    // Registration order. Ties, as in stores saved before it was recorded, are broken by name.
+    Unsorted,
+}
+
+// How CheckPermissions combines the required permissions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchMode {
+    #[default]
+    All,
+    Any,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    Grant,
+    Revoke,
+    Reset,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Create => "create",
+            AuditAction::Update => "update",
+            AuditAction::Delete => "delete",
+            AuditAction::Grant => "grant",
+            AuditAction::Revoke => "revoke",
+            AuditAction::Reset => "reset",
+        }
+    }
+}
+
+// One user mutation with the permission masks before and after it
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    // Unix timestamp (seconds)
+    pub timestamp: u64,
+    pub action: AuditAction,
+    pub user_id: String,
+    pub before: u64,
+    pub after: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrityViolationKind {
+    OrphanBits,
+    DuplicateEmail,
+    PermissionValue,
+}
+
+impl IntegrityViolationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntegrityViolationKind::OrphanBits => "orphan_bits",
+            IntegrityViolationKind::DuplicateEmail => "duplicate_email",
+            IntegrityViolationKind::PermissionValue => "permission_value",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IntegrityViolation {
+    pub kind: IntegrityViolationKind,
+    // User id, email or permission name the violation refers to
+    pub subject: String,
+    pub detail: String,
+}
@@ -0,0 +1,168 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use http_body_util::{BodyExt, Full};
+use tonic::body::BoxBody;
+use tonic::codegen::{http, BoxFuture, Service};
+use tonic::server::NamedService;
+use tonic::transport::{Identity, ServerTlsConfig};
+use tonic::Status;
+use tonic_health::pb::health_server::{Health, HealthServer};
+
+use crate::iam::iam_service_server::IamServiceServer;
+use crate::iam_manager::IamManager;
+use crate::logging::format_encoded_request;
+use crate::models::MatchMode;
+
+// RPCs callers may make without an API key: authenticating and checking permissions. Every other
+// IamService RPC, reads included, needs an admin key. The health service is never wrapped.
+pub const OPEN_RPCS: &[&str] = &["VerifyCredentials", "CheckPermissions"];
+
+// Requires every RPC outside OPEN_RPCS to carry an API key (`authorization: Bearer <key>` or
+// `x-api-key: <key>`) whose user holds the admin permissions. Wraps the service rather than using a tonic Interceptor,
+// which never sees the method being called.
+#[derive(Clone)]
+pub struct ApiKeyInterceptor<S> {
+    inner: S,
+    manager: Arc<IamManager>,
+    admin_permissions: Vec<String>,
+}
+
+impl<S> ApiKeyInterceptor<S> {
+    pub fn new(inner: S, manager: Arc<IamManager>, admin_permissions: Vec<String>) -> Self {
+        Self { inner, manager, admin_permissions }
+    }
+
+    // The status to reject the call with, None when it may proceed
+    fn denial(&self, headers: &http::HeaderMap) -> Option<Status> {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let key = header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| header("x-api-key"));
+        let Some(key) = key else {
+            return Some(Status::unauthenticated("Missing API key"));
+        };
+        let user_id = match self.manager.resolve_api_key(key.trim()) {
+            Ok(user_id) => user_id,
+            Err(e) => return Some(e.into()),
+        };
+        match self.manager.check_permissions(&user_id, &self.admin_permissions, MatchMode::All) {
+            Ok((true, _)) => None,
+            Ok((false, missing)) => Some(Status::permission_denied(format!("Missing permissions: {}", missing.join(", ")))),
+            Err(e) => Some(Status::permission_denied(e.to_string())),
+        }
+    }
+}
+
+impl<S: NamedService> NamedService for ApiKeyInterceptor<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S> Service<http::Request<BoxBody>> for ApiKeyInterceptor<S>
+where
+    S: Service<http::Request<BoxBody>, Response = http::Response<BoxBody>, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        let method = request.uri().path().rsplit('/').next().unwrap_or_default();
+        if !OPEN_RPCS.contains(&method) {
+            if let Some(status) = self.denial(request.headers()) {
+                return Box::pin(async move { Ok(status.into_http()) });
+            }
+        }
+        Box::pin(self.inner.call(request))
+    }
+}
+
+// Logs each request payload, redacted, at debug level before passing the call on. The body is
+// buffered and decoded only while debug records are enabled.
+#[derive(Clone)]
+pub struct RequestLogger<S> {
+    inner: S,
+}
+
+impl<S> RequestLogger<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S: NamedService> NamedService for RequestLogger<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S> Service<http::Request<BoxBody>> for RequestLogger<S>
+where
+    S: Service<http::Request<BoxBody>, Response = http::Response<BoxBody>, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        if !log::log_enabled!(log::Level::Debug) {
+            return Box::pin(self.inner.call(request));
+        }
+        // The service polled ready handles this call, a fresh clone the next one
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let body = match body.collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(status) => return Ok(status.into_http()),
+            };
+            // A gRPC frame: compression flag, 4-byte length, then the message
+            let method = parts.uri.path().rsplit('/').next().unwrap_or_default();
+            if let Some(line) = body.get(5..).filter(|_| body[0] == 0).and_then(|message| format_encoded_request(method, message)) {
+                log::debug!("{}", line);
+            }
+            inner.call(http::Request::from_parts(parts, tonic::body::boxed(Full::new(body)))).await
+        })
+    }
+}
+
+// grpc.health.v1.Health service reporting iam.IamService SERVING, or NOT_SERVING when the manager
+// is unavailable
+pub async fn health_service(manager: &IamManager) -> HealthServer<impl Health> {
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    if manager.is_available() {
+        health_reporter.set_serving::<IamServiceServer<IamManager>>().await;
+    } else {
+        health_reporter.set_not_serving::<IamServiceServer<IamManager>>().await;
+    }
+    health_service
+}
+
+// TLS configuration serving the PEM certificate chain and private key read from the given files
+pub fn tls_config(cert_path: &str, key_path: &str) -> Result<ServerTlsConfig, String> {
+    let cert = std::fs::read_to_string(cert_path)
+        .map_err(|e| format!("Failed to read TLS certificate {}: {}", cert_path, e))?;
+    let key = std::fs::read_to_string(key_path)
+        .map_err(|e| format!("Failed to read TLS key {}: {}", key_path, e))?;
+    Ok(ServerTlsConfig::new().identity(Identity::from_pem(cert, key)))
+}
+
+// grpc.reflection.v1 service describing iam.IamService, for grpcurl and Postman
+#[cfg(feature = "reflection")]
+pub fn reflection_service() -> Result<
+    tonic_reflection::server::v1::ServerReflectionServer<impl tonic_reflection::server::v1::ServerReflection>,
+    tonic_reflection::server::Error,
+> {
+    tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(crate::iam::FILE_DESCRIPTOR_SET)
+        .build_v1()
+}
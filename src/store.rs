@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::IamError;
+use crate::models::{User, Permission, Role};
+
+// Everything the manager needs to rebuild its state after a restart
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StoreSnapshot {
+    pub users: HashMap<String, User>,
+    pub permissions: HashMap<String, Permission>,
+    #[serde(default)]
+    pub roles: HashMap<String, Role>,
+    #[serde(default)]
+    pub reserved_bits: u64,
+}
+
+// Persistence backend, saved after every mutation
+pub trait Store: Send + Sync {
+    fn load(&self) -> Result<StoreSnapshot, IamError>;
+    fn save(&self, snapshot: &StoreSnapshot) -> Result<(), IamError>;
+}
+
+pub struct JsonFileStore {
+    path: PathBuf,
+}
+
+impl JsonFileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Store for JsonFileStore {
+    fn load(&self) -> Result<StoreSnapshot, IamError> {
+        let content = fs::read_to_string(&self.path)
+            .map_err(|e| IamError::Internal(format!("Failed to read store {}: {}", self.path.display(), e)))?;
+        serde_json::from_str(&content)
+            .map_err(|e| IamError::Internal(format!("Failed to parse store {}: {}", self.path.display(), e)))
+    }
+
+    // Writes to a temporary file then renames it, so a crash never leaves a partial store
+    fn save(&self, snapshot: &StoreSnapshot) -> Result<(), IamError> {
+        let content = serde_json::to_string_pretty(snapshot)
+            .map_err(|e| IamError::Internal(format!("Failed to serialize store: {}", e)))?;
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut file = File::create(&tmp_path)
+            .map_err(|e| IamError::Internal(format!("Failed to write store {}: {}", self.path.display(), e)))?;
+        file.write_all(content.as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(|e| IamError::Internal(format!("Failed to write store {}: {}", self.path.display(), e)))?;
+        fs::rename(&tmp_path, &self.path)
+            .map_err(|e| IamError::Internal(format!("Failed to write store {}: {}", self.path.display(), e)))
+    }
+}
@@ -1,51 +1,295 @@
-use std::collections::HashMap;
-use crate::models::Permission;
+use std::collections::{HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::error::IamError;
+use crate::models::{Permission, PermissionRegistry, RegistryConflict, SortOrder};
+
+// Permission names are stored and looked up in uppercase so matching is case-insensitive
+pub fn normalize_permission_name(name: &str) -> String {
+    name.to_uppercase()
+}
+
+// Normalized name for a new or renamed permission. Surrounding whitespace is ignored, as it is
+// when names are resolved, but the name may not be empty, contain inner whitespace or contain `*`,
+// which is reserved for wildcards.
+pub fn validate_permission_name(name: &str) -> Result<String, IamError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(IamError::InvalidArgument("Permission name cannot be empty".to_string()));
+    }
+    if name.chars().any(char::is_whitespace) {
+        return Err(IamError::InvalidArgument(format!("Permission name '{}' cannot contain whitespace", name)));
+    }
+    if name.contains('*') {
+        return Err(IamError::InvalidArgument(format!("Permission name '{}' cannot contain '*'", name)));
+    }
+    Ok(normalize_permission_name(name))
+}
 
 pub fn permission_names_to_bits(
     permissions: &HashMap<String, Permission>,
     names: &[String]
-) -> Result<u64, String> {
+) -> Result<u64, IamError> {
     let mut bits = 0u64;
+    let mut seen: HashSet<String> = HashSet::new();
+    // In the order given, for the error message
+    let mut unknown: Vec<String> = Vec::new();
 
+    // "read", "READ " and "READ" all resolve, once, to READ
     for name in names {
-        if let Some(permission) = permissions.get(name) {
-            bits |= permission.value;
-        } else {
-            return Err(format!("Permission '{}' not found", name));
+        let name = normalize_permission_name(name.trim());
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        match permissions.get(&name) {
+            Some(permission) => bits |= permission.value,
+            None => unknown.push(name),
         }
     }
 
-    Ok(bits)
+    // Every unknown name is reported at once
+    match unknown.as_slice() {
+        [] => Ok(bits),
+        [name] => Err(IamError::NotFound(format!("Permission '{}' not found", name))),
+        names => Err(IamError::NotFound(format!("Permissions not found: {}", names.join(", ")))),
+    }
+}
+
+// Replaces every name ending in `*` with the registered permissions sharing its prefix, sorted by name.
+// Other names are kept as given.
+pub fn expand_permission_wildcards(
+    permissions: &HashMap<String, Permission>,
+    names: &[String]
+) -> Result<Vec<String>, IamError> {
+    let mut expanded = Vec::new();
+    for name in names {
+        let Some(prefix) = name.trim().strip_suffix('*') else {
+            expanded.push(name.clone());
+            continue;
+        };
+        let prefix = normalize_permission_name(prefix);
+        let mut matched: Vec<String> = permissions.keys()
+            .filter(|key| key.starts_with(&prefix))
+            .cloned()
+            .collect();
+        if matched.is_empty() {
+            return Err(IamError::InvalidArgument(format!("Wildcard '{}' matches no permission", name.trim())));
+        }
+        matched.sort();
+        expanded.extend(matched);
+    }
+    Ok(expanded)
 }
 
 pub fn bits_to_permission_names(
     permissions: &HashMap<String, Permission>,
     bits: u64
-) -> Result<Vec<String>, String> {
-    let mut names = Vec::new();
+) -> Result<Vec<String>, IamError> {
+    bits_to_permission_names_with_order(permissions, bits, SortOrder::Alphabetical, false)
+}
+
+// A composite permission is matched whenever all of its bits are set. By default it is listed
+// alongside its members. With collapse_composites, the broadest matched composites are kept
+// (ties broken by name) and every permission they cover is dropped.
+pub fn bits_to_permission_names_with_order(
+    permissions: &HashMap<String, Permission>,
+    bits: u64,
+    order: SortOrder,
+    collapse_composites: bool
+) -> Result<Vec<String>, IamError> {
+    let mut matched: Vec<&Permission> = permissions.values()
+        .filter(|permission| (bits & permission.value) == permission.value)
+        .collect();
+
+    if collapse_composites {
+        let mut composites: Vec<&Permission> = matched.iter().copied().filter(|p| p.composite).collect();
+        composites.sort_by(|a, b| b.value.count_ones().cmp(&a.value.count_ones()).then_with(|| a.name.cmp(&b.name)));
+
+        let mut covered = 0u64;
+        let mut kept: Vec<&str> = Vec::new();
+        for composite in composites {
+            if composite.value & !covered != 0 {
+                covered |= composite.value;
+                kept.push(&composite.name);
+            }
+        }
+        matched.retain(|p| if p.composite { kept.contains(&p.name.as_str()) } else { p.value & covered == 0 });
+    }
+
+    match order {
+        SortOrder::Alphabetical => matched.sort_by(|a, b| a.name.cmp(&b.name)),
+        SortOrder::ByValue => matched.sort_by(|a, b| a.value.cmp(&b.value).then_with(|| a.name.cmp(&b.name))),
+        SortOrder::Unsorted => matched.sort_by(|a, b| a.sequence.cmp(&b.sequence).then_with(|| a.name.cmp(&b.name))),
+    }
+
+    Ok(matched.into_iter().map(|permission| permission.name.clone()).collect())
+}
+
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn compute_missing_bits(held: u64, required: u64) -> u64 {
+    required & !held
+}
+
+// Smallest single bit not present in used_values, so freed gaps are reused first.
+// Multi-bit (composite) values never match a single bit and so never block allocation.
+pub fn find_next_available_bit(used_values: &[u64]) -> Result<u64, IamError> {
+    (0..64)
+        .map(|position| 1u64 << position)
+        .find(|value| !used_values.contains(value))
+        .ok_or_else(|| IamError::ResourceExhausted("No more permission slots available".to_string()))
+}
 
-    for permission in permissions.values() {
-        if (bits & permission.value) == permission.value {
-            names.push(permission.name.clone());
+// Sequence number for the next permission registered in the registry
+pub fn next_sequence(permissions: &HashMap<String, Permission>) -> u64 {
+    permissions.values().map(|permission| permission.sequence + 1).max().unwrap_or(0)
+}
+
+pub fn registry_mask(permissions: &HashMap<String, Permission>) -> u64 {
+    permissions.values().fold(0u64, |mask, permission| mask | permission.value)
+}
+
+pub fn permissions_complement(bits: u64, universe: u64) -> u64 {
+    universe & !bits
+}
+
+pub fn orphan_bits(permissions: &HashMap<String, Permission>, bits: u64) -> u64 {
+    bits & !registry_mask(permissions)
+}
+
+// Lightweight shape check: a single '@' between non-empty parts and no inner whitespace.
+// Surrounding whitespace is ignored, as it is when matching emails.
+pub fn validate_email(email: &str) -> Result<(), IamError> {
+    let trimmed = email.trim();
+    let valid = match trimmed.split_once('@') {
+        Some((local, domain)) => !local.is_empty()
+            && !domain.is_empty()
+            && !domain.contains('@')
+            && !trimmed.chars().any(char::is_whitespace),
+        None => false,
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(IamError::InvalidArgument(format!("Invalid email format: '{}'", email)))
+    }
+}
+
+// Reads the cost factor out of a "$2b$<cost>$<salt+hash>" bcrypt string
+pub fn bcrypt_cost(password_hash: &str) -> Result<u32, IamError> {
+    password_hash.split('$')
+        .nth(2)
+        .and_then(|cost| cost.parse::<u32>().ok())
+        .ok_or_else(|| IamError::Internal("Stored password hash is not a bcrypt hash".to_string()))
+}
+
+// Range of cost factors accepted by bcrypt
+pub fn validate_bcrypt_cost(cost: u32) -> Result<u32, IamError> {
+    if (4..=31).contains(&cost) {
+        Ok(cost)
+    } else {
+        Err(IamError::InvalidArgument(format!("Invalid bcrypt cost {}: must be between 4 and 31", cost)))
+    }
+}
+
+pub fn split_bits(bits: u64) -> Vec<u64> {
+    (0..64)
+        .map(|position| 1u64 << position)
+        .filter(|value| bits & value != 0)
+        .collect()
+}
+
+// Every permission value must be a single bit and no two permissions may share it.
+// Composites are instead required to only cover bits owned by single-bit permissions.
+pub fn validate_permission_values(permissions: &HashMap<String, Permission>) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    let mut sorted: Vec<&Permission> = permissions.values().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let single_bits = sorted.iter()
+        .filter(|permission| !permission.composite)
+        .fold(0u64, |mask, permission| mask | permission.value);
+
+    let mut seen: HashMap<u64, &str> = HashMap::new();
+    for permission in sorted {
+        if permission.composite {
+            let uncovered = permission.value & !single_bits;
+            if permission.value == 0 || uncovered != 0 {
+                errors.push(format!("Composite permission '{}' has value {} not covered by registered permissions", permission.name, permission.value));
+            }
+            continue;
+        }
+        if !permission.value.is_power_of_two() {
+            errors.push(format!("Permission '{}' has value {} which is not a single bit", permission.name, permission.value));
+        }
+        if let Some(other) = seen.insert(permission.value, &permission.name) {
+            errors.push(format!("Permissions '{}' and '{}' share value {}", other, permission.name, permission.value));
         }
     }
 
-    names.sort();
-    Ok(names)
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
 }
 
-pub fn find_next_available_bit(used_values: &[u64]) -> Result<u64, String> {
-    let mut sorted_values = used_values.to_vec();
-    sorted_values.sort();
+// Adds the right registry's permissions to the left one. A name already on the left must keep its
+// value, and no value may end up under two names, whether the clash is with the left registry or
+// inside the right one. Added permissions are sequenced after the left registry's, in the right
+// registry's own order.
+pub fn merge_registries(
+    left: &PermissionRegistry,
+    right: &PermissionRegistry
+) -> Result<PermissionRegistry, Vec<RegistryConflict>> {
+    let mut merged = left.clone();
+    let mut conflicts = Vec::new();
+    let mut added: Vec<&Permission> = Vec::new();
+
+    let mut incoming: Vec<&Permission> = right.values().collect();
+    incoming.sort_by(|a, b| a.name.cmp(&b.name));
 
-    let mut next_value = 1u64;
-    for &used_value in &sorted_values {
-        if next_value == used_value {
-            next_value = next_value.checked_mul(2).ok_or("No more permission slots available")?;
-        } else {
-            break;
+    for permission in incoming {
+        if let Some(existing) = left.get(&permission.name) {
+            if existing.value != permission.value {
+                conflicts.push(RegistryConflict::Value {
+                    name: permission.name.clone(),
+                    left: existing.value,
+                    right: permission.value,
+                });
+            }
+            continue;
+        }
+
+        if let Some(existing) = merged.values().find(|p| p.value == permission.value) {
+            conflicts.push(RegistryConflict::Name {
+                value: permission.value,
+                left: existing.name.clone(),
+                right: permission.name.clone(),
+            });
+            continue;
+        }
+
+        merged.insert(permission.name.clone(), permission.clone());
+        added.push(permission);
+    }
+
+    let first = next_sequence(left);
+    added.sort_by_key(|permission| permission.sequence);
+    for (offset, permission) in added.into_iter().enumerate() {
+        if let Some(merged_permission) = merged.get_mut(&permission.name) {
+            merged_permission.sequence = first + offset as u64;
         }
     }
 
-    Ok(next_value)
-}
\ No newline at end of file
+    if conflicts.is_empty() {
+        Ok(merged)
+    } else {
+        Err(conflicts)
+    }
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use bitflags_iam::AuditAction;
+
+#[test]
+fn audit_log_filters_by_user_and_keeps_the_latest_entries() {
+    let manager = common::manager();
+    let first = common::create_user(&manager, "first@example.com", &["READ"]);
+    let second = common::create_user(&manager, "second@example.com", &[]);
+    manager.grant_permission(&first.id, "WRITE").unwrap();
+    manager.grant_permission(&second.id, "DELETE").unwrap();
+    manager.revoke_permission(&first.id, "READ").unwrap();
+
+    let entries = manager.get_audit_log(None, 0).unwrap();
+    assert_eq!(entries.len(), 5);
+
+    let first_entries: Vec<(AuditAction, Vec<String>, Vec<String>)> = manager.get_audit_log(Some(&first.id), 2).unwrap()
+        .into_iter()
+        .map(|(entry, before, after)| {
+            assert_eq!(entry.user_id, first.id);
+            (entry.action, before, after)
+        })
+        .collect();
+    assert_eq!(first_entries, vec![
+        (AuditAction::Grant, common::names(&["READ"]), common::names(&["READ", "WRITE"])),
+        (AuditAction::Revoke, common::names(&["READ", "WRITE"]), common::names(&["WRITE"])),
+    ]);
+}
+
+#[test]
+fn audit_log_is_bounded() {
+    let manager = common::manager().with_audit_capacity(2);
+    let user = common::create_user(&manager, "user@example.com", &[]);
+    manager.grant_permission(&user.id, "READ").unwrap();
+    manager.revoke_permission(&user.id, "READ").unwrap();
+
+    let actions: Vec<AuditAction> = manager.get_audit_log(None, 0).unwrap().into_iter().map(|(entry, _, _)| entry.action).collect();
+    assert_eq!(actions, vec![AuditAction::Grant, AuditAction::Revoke]);
+}
@@ -0,0 +1,233 @@
+mod common;
+
+use std::collections::HashMap;
+
+use bitflags_iam::iam::iam_service_server::IamService;
+use bitflags_iam::iam::{BatchCheckEntry, BatchCheckPermissionsRequest, CheckPermissionsRequest, GetMetricsRequest};
+use bitflags_iam::{IamError, IamManager, IntegrityViolationKind, MatchMode, MetricsSnapshot, Permission, StoreSnapshot};
+use tonic::{Code, Request};
+
+fn check_request(user_id: &str, required: &[&str]) -> Request<CheckPermissionsRequest> {
+    Request::new(CheckPermissionsRequest {
+        user_id: user_id.to_string(),
+        required_permissions: common::names(required),
+        ..Default::default()
+    })
+}
+
+#[tokio::test]
+async fn registry_load_failure_denies_every_check() {
+    let path = std::env::temp_dir().join(format!("bitflags-iam-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(&path, "{ not json").unwrap();
+    let error = IamManager::load_from_path(&path).err().expect("corrupt store rejected");
+    std::fs::remove_file(&path).unwrap();
+    let manager = IamManager::unavailable(error.to_string());
+
+    for required in [&["READ"][..], &["READ", "WRITE"], &["READ_*"]] {
+        let response = IamService::check_permissions(&manager, check_request("any-user", required)).await.unwrap().into_inner();
+        assert!(!response.has_permissions);
+        assert!(!response.success);
+    }
+
+    let status = IamService::check_permissions(&manager.with_status_codes(true), check_request("any-user", &["READ"]))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+}
+
+#[test]
+fn users_missing_permission_excludes_holders() {
+    let manager = common::manager();
+    let holder = common::create_user(&manager, "holder@example.com", &["READ", "WRITE"]);
+    let reader = common::create_user(&manager, "reader@example.com", &["READ"]);
+    let empty = common::create_user(&manager, "empty@example.com", &[]);
+
+    let mut expected = vec![reader.id, empty.id];
+    expected.sort();
+    let missing: Vec<String> = manager.get_users_missing_permission("WRITE").unwrap().into_iter().map(|(user, _)| user.id).collect();
+    assert_eq!(missing, expected);
+    assert!(!missing.contains(&holder.id));
+    assert!(manager.get_users_missing_permission("UNKNOWN").is_err());
+}
+
+#[test]
+fn check_integrity_reports_every_injected_violation() {
+    let orphaned = common::user("orphaned@example.com", 1 | 128);
+    let first = common::user("shared@example.com", 1);
+    let second = common::user(" Shared@Example.com", 2);
+    let mut permissions: Vec<Permission> = common::manager().list_permissions().unwrap();
+    permissions.push(Permission::new("ALIAS".to_string(), 1));
+    let manager = common::load_snapshot(StoreSnapshot {
+        permissions: permissions.into_iter().map(|p| (p.name.clone(), p)).collect(),
+        users: [orphaned.clone(), first, second].into_iter().map(|u| (u.id.clone(), u)).collect(),
+        ..Default::default()
+    });
+
+    let violations = manager.check_integrity().unwrap();
+    let kinds: Vec<(IntegrityViolationKind, String)> = violations.iter().map(|v| (v.kind, v.subject.clone())).collect();
+    assert_eq!(violations.len(), 3);
+    assert!(kinds.contains(&(IntegrityViolationKind::PermissionValue, "registry".to_string())));
+    assert!(kinds.contains(&(IntegrityViolationKind::OrphanBits, orphaned.id.clone())));
+    assert!(kinds.contains(&(IntegrityViolationKind::DuplicateEmail, "shared@example.com".to_string())));
+
+    // Reported only, nothing is repaired
+    assert_eq!(manager.get_user(&orphaned.id).unwrap().permissions, 1 | 128);
+    assert!(common::manager().check_integrity().unwrap().is_empty());
+}
+
+#[test]
+fn registry_values_must_be_distinct_single_bits() {
+    let registry: HashMap<String, Permission> = [("READ", 1), ("ALIAS", 1), ("BOTH", 6)]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), Permission::new(name.to_string(), value)))
+        .collect();
+
+    assert_eq!(bitflags_iam::validate_permission_values(&registry).unwrap_err(), vec![
+        "Permission 'BOTH' has value 6 which is not a single bit".to_string(),
+        "Permissions 'ALIAS' and 'READ' share value 1".to_string(),
+    ]);
+}
+
+#[test]
+fn repair_integrity_clears_orphan_bits() {
+    let orphaned = common::user("orphaned@example.com", 1 | 128);
+    let clean = common::user("clean@example.com", 1);
+    let manager = common::load_with_users(vec![orphaned.clone(), clean.clone()]);
+
+    let repaired = manager.repair_integrity().unwrap();
+    assert_eq!(repaired.len(), 1);
+    assert_eq!(repaired[0].subject, orphaned.id);
+    assert_eq!(manager.get_user(&orphaned.id).unwrap().permissions, 1);
+    assert_eq!(manager.get_user(&clean.id).unwrap().permissions, 1);
+    assert!(manager.repair_integrity().unwrap().is_empty());
+}
+
+#[test]
+fn any_mode_needs_a_single_held_permission() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ"]);
+    let required = common::names(&["READ", "DELETE"]);
+
+    assert_eq!(manager.check_permissions(&user.id, &required, MatchMode::All).unwrap(), (false, common::names(&["DELETE"])));
+    assert_eq!(manager.check_permissions(&user.id, &required, MatchMode::Any).unwrap(), (true, vec![]));
+    assert_eq!(manager.check_permissions(&user.id, &common::names(&["WRITE", "DELETE"]), MatchMode::Any).unwrap(), (false, common::names(&["DELETE", "WRITE"])));
+    assert!(manager.check_permissions(&user.id, &common::names(&["READ", "AUDIT"]), MatchMode::Any).is_err());
+}
+
+#[tokio::test]
+async fn check_mode_defaults_to_all() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ"]);
+
+    let response = IamService::check_permissions(&manager, check_request(&user.id, &["READ", "DELETE"])).await.unwrap().into_inner();
+    assert!(!response.has_permissions);
+    assert_eq!(response.missing_permissions, common::names(&["DELETE"]));
+}
+
+#[tokio::test]
+async fn batch_check_fails_only_the_entries_of_unknown_users() {
+    let manager = common::manager();
+    let reader = common::create_user(&manager, "reader@example.com", &["READ"]);
+    let entry = |user_id: &str, required: &[&str]| BatchCheckEntry {
+        user_id: user_id.to_string(),
+        required_permissions: common::names(required),
+        ..Default::default()
+    };
+
+    let response = IamService::batch_check_permissions(&manager, Request::new(BatchCheckPermissionsRequest {
+        entries: vec![entry(&reader.id, &["READ"]), entry("missing", &["READ"]), entry(&reader.id, &["READ", "WRITE"])],
+    }))
+    .await
+    .unwrap()
+    .into_inner();
+
+    let results: Vec<(&str, bool, bool, Vec<String>)> = response.results.iter()
+        .map(|r| (r.user_id.as_str(), r.success, r.has_permissions, r.missing_permissions.clone()))
+        .collect();
+    assert_eq!(results, vec![
+        (reader.id.as_str(), true, true, vec![]),
+        ("missing", false, false, vec![]),
+        (reader.id.as_str(), true, false, common::names(&["WRITE"])),
+    ]);
+    assert_eq!(response.results[1].message, "User not found");
+}
+
+#[test]
+fn mask_check_agrees_with_the_name_check() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ", "WRITE"]);
+    let registry = manager.list_permissions().unwrap();
+
+    for required in [&[][..], &["READ"], &["READ", "WRITE"], &["READ", "DELETE"], &["EXECUTE", "DELETE"]] {
+        let mask = registry.iter()
+            .filter(|p| required.contains(&p.name.as_str()))
+            .fold(0, |mask, p| mask | p.value);
+        let (by_names, missing_names) = manager.check_permissions(&user.id, &common::names(required), MatchMode::All).unwrap();
+        let (by_mask, missing_mask) = manager.check_permissions_by_mask(&user.id, mask).unwrap();
+
+        assert_eq!(by_mask, by_names);
+        let missing_names_mask = registry.iter()
+            .filter(|p| missing_names.contains(&p.name))
+            .fold(0, |mask, p| mask | p.value);
+        assert_eq!(missing_mask, missing_names_mask);
+    }
+}
+
+#[tokio::test]
+async fn empty_requirement_passes_only_for_existing_users() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &[]);
+
+    for mode in [MatchMode::All, MatchMode::Any] {
+        assert_eq!(manager.check_permissions(&user.id, &[], mode).unwrap(), (true, vec![]));
+        assert_eq!(manager.check_permissions("missing", &[], mode).unwrap_err(), IamError::NotFound("User not found".to_string()));
+    }
+
+    let held = IamService::check_permissions(&manager, check_request(&user.id, &[])).await.unwrap().into_inner();
+    assert!(held.success && held.has_permissions);
+    let missing = IamService::check_permissions(&manager, check_request("missing", &[])).await.unwrap().into_inner();
+    assert!(!missing.success);
+    assert!(!missing.has_permissions);
+}
+
+#[tokio::test]
+async fn metrics_count_checks_and_attribute_denials() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ"]);
+
+    for (user_id, required) in [(user.id.as_str(), &["READ"][..]), (&user.id, &["READ", "DELETE"]), (&user.id, &["WRITE", "DELETE"]), ("missing", &["READ"])] {
+        IamService::check_permissions(&manager, check_request(user_id, required)).await.unwrap();
+    }
+
+    assert_eq!(manager.metrics(), MetricsSnapshot {
+        checks: 4,
+        allowed: 1,
+        denied: 2,
+        denials: vec![("DELETE".to_string(), 2), ("WRITE".to_string(), 1)],
+    });
+    let response = IamService::get_metrics(&manager, Request::new(GetMetricsRequest::default())).await.unwrap().into_inner();
+    assert_eq!((response.checks, response.allowed, response.denied), (4, 1, 2));
+    let denials: Vec<(String, u64)> = response.denials.into_iter().map(|d| (d.permission_name, d.count)).collect();
+    assert_eq!(denials, manager.metrics().denials);
+}
+
+#[test]
+fn wildcards_expand_to_every_permission_sharing_the_prefix() {
+    let manager = common::manager();
+    for name in ["BILLING_READ", "BILLING_WRITE", "REPORTS_READ"] {
+        common::add_permission(&manager, name);
+    }
+    let reader = common::create_user(&manager, "reader@example.com", &["BILLING_READ"]);
+    let billing = common::create_user(&manager, "billing@example.com", &["BILLING_READ", "BILLING_WRITE"]);
+    let wildcard = common::names(&["billing_*"]);
+
+    assert_eq!(manager.check_permissions(&reader.id, &wildcard, MatchMode::All).unwrap(), (false, common::names(&["BILLING_WRITE"])));
+    assert_eq!(manager.check_permissions(&reader.id, &wildcard, MatchMode::Any).unwrap(), (true, vec![]));
+    assert_eq!(manager.check_permissions(&billing.id, &wildcard, MatchMode::All).unwrap(), (true, vec![]));
+    assert_eq!(manager.check_permissions(&billing.id, &common::names(&["BILLING_*", "REPORTS_*"]), MatchMode::All).unwrap(), (false, common::names(&["REPORTS_READ"])));
+
+    assert_eq!(
+        manager.check_permissions(&reader.id, &common::names(&["AUDIT_*"]), MatchMode::Any).unwrap_err(),
+        IamError::InvalidArgument("Wildcard 'AUDIT_*' matches no permission".to_string())
+    );
+}
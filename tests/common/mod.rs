@@ -0,0 +1,47 @@
+#![allow(dead_code)]
+
+use bitflags_iam::{IamManager, JsonFileStore, Permission, Store, StoreSnapshot, User};
+
+// Lowest bcrypt cost, so creating users does not dominate the test run
+pub fn manager() -> IamManager {
+    IamManager::new().with_bcrypt_cost(4).expect("valid bcrypt cost")
+}
+
+pub fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+pub fn create_user(manager: &IamManager, email: &str, permissions: &[&str]) -> User {
+    manager
+        .create_user("Test User".to_string(), email.to_string(), "password123".to_string(), names(permissions))
+        .expect("user created")
+}
+
+pub fn add_permission(manager: &IamManager, name: &str) -> Permission {
+    manager
+        .add_permission(name.to_string(), String::new(), String::new(), None, None)
+        .expect("permission added")
+}
+
+// Manager restored from the snapshot, which may break invariants the manager itself keeps
+// (unregistered bits, duplicate emails, ...). The store file is removed once loaded.
+pub fn load_snapshot(snapshot: StoreSnapshot) -> IamManager {
+    let path = std::env::temp_dir().join(format!("bitflags-iam-{}.json", uuid::Uuid::new_v4()));
+    JsonFileStore::new(&path).save(&snapshot).expect("store written");
+    let manager = IamManager::load_from_path(&path).expect("store loaded");
+    std::fs::remove_file(path).expect("store removed");
+    manager
+}
+
+// Default registry with the given users
+pub fn load_with_users(users: Vec<User>) -> IamManager {
+    load_snapshot(StoreSnapshot {
+        permissions: manager().list_permissions().unwrap().into_iter().map(|p| (p.name.clone(), p)).collect(),
+        users: users.into_iter().map(|user| (user.id.clone(), user)).collect(),
+        ..Default::default()
+    })
+}
+
+pub fn user(email: &str, permissions: u64) -> User {
+    User::new_with_cost("Test User".to_string(), email.to_string(), "password123".to_string(), permissions, 4).expect("user built")
+}
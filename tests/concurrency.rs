@@ -0,0 +1,85 @@
+mod common;
+
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn readers_and_writers_taking_several_locks_do_not_deadlock() {
+    let manager = Arc::new(common::manager());
+    let user = common::create_user(&manager, "user@example.com", &["READ"]);
+
+    let checker = {
+        let manager = Arc::clone(&manager);
+        let user_id = user.id.clone();
+        thread::spawn(move || {
+            for _ in 0..500 {
+                manager.check_permissions(&user_id, &common::names(&["READ"]), Default::default()).unwrap();
+                manager.get_user_with_permission_names(&user_id).unwrap();
+                manager.check_integrity().unwrap();
+            }
+        })
+    };
+    let writer = {
+        let manager = Arc::clone(&manager);
+        thread::spawn(move || {
+            for i in 0..500 {
+                let name = format!("TEMP_{}", i);
+                common::add_permission(&manager, &name);
+                manager.remove_permission(&name).unwrap();
+            }
+        })
+    };
+
+    checker.join().unwrap();
+    writer.join().unwrap();
+}
+
+#[test]
+fn concurrent_add_permission_assigns_distinct_bits() {
+    let manager = Arc::new(common::manager());
+
+    let handles: Vec<_> = (0..8)
+        .map(|t| {
+            let manager = Arc::clone(&manager);
+            thread::spawn(move || (0..7).map(|i| common::add_permission(&manager, &format!("P_{}_{}", t, i)).value).collect::<Vec<_>>())
+        })
+        .collect();
+    let mut values: Vec<u64> = handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect();
+
+    assert_eq!(values.len(), 56);
+    assert!(values.iter().all(|value| value.is_power_of_two() && *value > 8));
+    values.sort();
+    values.dedup();
+    assert_eq!(values.len(), 56);
+    assert!(manager.check_integrity().unwrap().is_empty());
+}
+
+#[test]
+fn concurrent_grants_to_one_user_lose_no_update() {
+    let manager = Arc::new(common::manager());
+    let names: Vec<String> = (0..40).map(|i| common::add_permission(&manager, &format!("P_{}", i)).name).collect();
+    let user = common::create_user(&manager, "user@example.com", &[]);
+
+    let handles: Vec<_> = names
+        .chunks(5)
+        .map(|chunk| {
+            let manager = Arc::clone(&manager);
+            let user_id = user.id.clone();
+            let chunk = chunk.to_vec();
+            thread::spawn(move || {
+                for name in chunk {
+                    manager.grant_permission(&user_id, &name).unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let (_, held, _) = manager.get_user_with_permission_names(&user.id).unwrap().unwrap();
+    let mut expected = names.clone();
+    expected.sort();
+    assert_eq!(held, expected);
+    assert_eq!(manager.get_audit_log(Some(&user.id), 0).unwrap().len(), 41);
+}
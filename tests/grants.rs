@@ -0,0 +1,211 @@
+mod common;
+
+use std::time::Duration;
+
+use bitflags_iam::iam::iam_service_server::IamService;
+use bitflags_iam::iam::{GetUserPermissionMaskRequest, ResetUserPermissionsRequest};
+use bitflags_iam::{AuditAction, IamError, MatchMode, StoreSnapshot};
+use tonic::{Code, Request};
+
+#[test]
+fn max_holders_rejects_grants_until_a_holder_is_dropped() {
+    let manager = common::manager();
+    manager.add_permission("ADMIN".to_string(), String::new(), String::new(), Some(2), None).unwrap();
+    let first = common::create_user(&manager, "first@example.com", &["ADMIN"]);
+    let second = common::create_user(&manager, "second@example.com", &[]);
+    let third = common::create_user(&manager, "third@example.com", &[]);
+    manager.update_user_permissions(&second.id, common::names(&["ADMIN"])).unwrap();
+
+    let rejected = manager.update_user_permissions(&third.id, common::names(&["READ", "ADMIN"])).unwrap_err();
+    assert_eq!(rejected, IamError::ResourceExhausted("Permission 'ADMIN' has reached its maximum of 2 holders".to_string()));
+    let created = manager.create_user("Test User".to_string(), "fourth@example.com".to_string(), "password123".to_string(), common::names(&["ADMIN"]));
+    assert_eq!(created.unwrap_err(), rejected);
+    assert!(!manager.check_permissions(&third.id, &common::names(&["ADMIN"]), Default::default()).unwrap().0);
+
+    // A holder keeping its grant does not count against itself
+    manager.update_user_permissions(&first.id, common::names(&["ADMIN", "READ"])).unwrap();
+    manager.update_user_permissions(&first.id, common::names(&["READ"])).unwrap();
+    manager.update_user_permissions(&third.id, common::names(&["ADMIN"])).unwrap();
+    assert!(manager.check_permissions(&third.id, &common::names(&["ADMIN"]), Default::default()).unwrap().0);
+}
+
+#[test]
+fn max_holders_applies_to_every_granting_path() {
+    let manager = common::manager();
+    manager.add_permission("ADMIN".to_string(), String::new(), String::new(), Some(1), None).unwrap();
+    manager.create_role("OPS".to_string(), &common::names(&["ADMIN"])).unwrap();
+    let holder = common::create_user(&manager, "holder@example.com", &["ADMIN"]);
+    let other = common::create_user(&manager, "other@example.com", &[]);
+
+    let full = |result: Result<(), IamError>| matches!(result, Err(IamError::ResourceExhausted(_)));
+    assert!(full(manager.update_user_permissions(&other.id, common::names(&["ADMIN"])).map(|_| ())));
+    assert!(full(manager.assign_role(&other.id, "OPS").map(|_| ())));
+    assert!(full(manager.grant_temporary_permission(&other.id, "ADMIN", Duration::from_secs(60)).map(|_| ())));
+    assert!(full(manager
+        .create_user("Late".to_string(), "late@example.com".to_string(), "password123".to_string(), common::names(&["ADMIN"]))
+        .map(|_| ())));
+
+    // The current holder is not counted against itself
+    manager.grant_permission(&holder.id, "ADMIN").unwrap();
+}
+
+#[test]
+fn expired_temporary_grant_is_no_longer_held() {
+    let manager = common::manager();
+    let admin = manager.add_permission("ADMIN".to_string(), String::new(), String::new(), Some(1), None).unwrap();
+    let temporary = common::create_user(&manager, "temporary@example.com", &["READ"]);
+    let other = common::create_user(&manager, "other@example.com", &[]);
+    let (names, _) = manager.grant_temporary_permission(&temporary.id, "ADMIN", Duration::from_secs(3600)).unwrap();
+    assert_eq!(names, common::names(&["ADMIN", "READ"]));
+    assert!(matches!(manager.grant_permission(&other.id, "ADMIN"), Err(IamError::ResourceExhausted(_))));
+
+    // The same grant once its expiry has passed
+    let mut temporary = common::user("temporary@example.com", 1 | admin.value);
+    temporary.expiries.insert(admin.value, 1);
+    let other = common::user("other@example.com", 0);
+    let manager = common::load_snapshot(StoreSnapshot {
+        permissions: manager.list_permissions().unwrap().into_iter().map(|p| (p.name.clone(), p)).collect(),
+        users: [&temporary, &other].into_iter().map(|user| (user.id.clone(), user.clone())).collect(),
+        ..Default::default()
+    });
+
+    assert!(!manager.check_permissions(&temporary.id, &common::names(&["ADMIN"]), Default::default()).unwrap().0);
+    let (_, names) = manager.verify_credentials("temporary@example.com", "password123").unwrap();
+    assert_eq!(names, common::names(&["READ"]));
+    assert_eq!(manager.describe_permission("ADMIN").unwrap().1, 0);
+    // The expired holder no longer counts towards max_holders
+    manager.grant_permission(&other.id, "ADMIN").unwrap();
+}
+
+#[test]
+fn batch_grant_reports_each_user() {
+    let manager = common::manager();
+    let first = common::create_user(&manager, "first@example.com", &["READ"]);
+    let second = common::create_user(&manager, "second@example.com", &[]);
+    let user_ids = vec![first.id.clone(), "missing".to_string(), second.id.clone()];
+
+    let results = manager.batch_grant_permissions(&user_ids, &common::names(&["WRITE", "EXECUTE"])).unwrap();
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0], (first.id.clone(), Ok(common::names(&["EXECUTE", "READ", "WRITE"]))));
+    assert_eq!(results[1], ("missing".to_string(), Err(IamError::NotFound("User not found".to_string()))));
+    assert_eq!(results[2], (second.id.clone(), Ok(common::names(&["EXECUTE", "WRITE"]))));
+    assert_eq!(manager.get_user(&first.id).unwrap().permissions, 7);
+    assert_eq!(manager.get_user(&second.id).unwrap().permissions, 6);
+}
+
+#[test]
+fn additive_only_rejects_updates_that_remove_bits() {
+    let manager = common::manager().with_additive_only(true);
+    let user = common::create_user(&manager, "user@example.com", &["READ", "WRITE"]);
+
+    let rejected = manager.update_user_permissions(&user.id, common::names(&["READ", "EXECUTE"])).unwrap_err();
+    assert_eq!(rejected, IamError::FailedPrecondition("Additive-only mode: update would remove held permissions (WRITE), revoke them explicitly instead".to_string()));
+    assert_eq!(manager.get_user(&user.id).unwrap().permissions, 3);
+
+    let (_, added, removed) = manager.update_user_permissions(&user.id, common::names(&["READ", "WRITE", "EXECUTE"])).unwrap();
+    assert_eq!((added, removed), (common::names(&["EXECUTE"]), vec![]));
+    // Explicit revocation still works
+    manager.revoke_permission(&user.id, "WRITE").unwrap();
+    assert_eq!(manager.get_user(&user.id).unwrap().permissions, 5);
+}
+
+#[test]
+fn assigning_a_role_adds_its_bits() {
+    let manager = common::manager();
+    let role = manager.create_role("EDITOR".to_string(), &common::names(&["READ", "WRITE"])).unwrap();
+    assert_eq!(role.bits, 3);
+    let user = common::create_user(&manager, "user@example.com", &["EXECUTE"]);
+
+    assert_eq!(manager.assign_role(&user.id, "EDITOR").unwrap().permissions, 7);
+    assert_eq!(manager.list_roles().unwrap().into_iter().map(|(role, names)| (role.name, names)).collect::<Vec<_>>(), vec![("EDITOR".to_string(), common::names(&["READ", "WRITE"]))]);
+
+    let unknown = manager.create_role("AUDITOR".to_string(), &common::names(&["READ", "AUDIT"])).unwrap_err();
+    assert_eq!(unknown, IamError::NotFound("Permission 'AUDIT' not found".to_string()));
+    assert_eq!(manager.assign_role(&user.id, "MISSING").unwrap_err(), IamError::NotFound("Role not found".to_string()));
+}
+
+#[test]
+fn grant_and_revoke_touch_a_single_bit() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ", "EXECUTE"]);
+
+    assert_eq!(manager.grant_permission(&user.id, "write").unwrap(), common::names(&["EXECUTE", "READ", "WRITE"]));
+    assert_eq!(manager.revoke_permission(&user.id, "EXECUTE").unwrap(), common::names(&["READ", "WRITE"]));
+    // Revoking a permission the user lacks changes nothing
+    assert_eq!(manager.revoke_permission(&user.id, "DELETE").unwrap(), common::names(&["READ", "WRITE"]));
+    assert_eq!(manager.get_user(&user.id).unwrap().permissions, 3);
+
+    assert_eq!(manager.grant_permission(&user.id, "AUDIT").unwrap_err(), IamError::NotFound("Permission 'AUDIT' not found".to_string()));
+    assert_eq!(manager.revoke_permission("missing", "READ").unwrap_err(), IamError::NotFound("User not found".to_string()));
+}
+
+#[test]
+fn permission_update_reports_added_and_removed_names() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ", "WRITE"]);
+
+    let (updated, added, removed) = manager.update_user_permissions(&user.id, common::names(&["READ", "DELETE", "EXECUTE"])).unwrap();
+    assert_eq!(added, common::names(&["DELETE", "EXECUTE"]));
+    assert_eq!(removed, common::names(&["WRITE"]));
+    assert_eq!(updated.permissions, 1 | 4 | 8);
+}
+
+#[tokio::test]
+async fn permission_mask_is_the_or_of_held_values() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ", "DELETE"]);
+    let registry = manager.list_permissions().unwrap();
+    let value = |name: &str| registry.iter().find(|p| p.name == name).unwrap().value;
+
+    let response = IamService::get_user_permission_mask(&manager, Request::new(GetUserPermissionMaskRequest { user_id: user.id.clone() }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.success);
+    assert_eq!(response.permission_mask, value("READ") | value("DELETE"));
+    assert_eq!(response.registry_mask, registry.iter().fold(0, |mask, p| mask | p.value));
+
+    let status = IamService::get_user_permission_mask(&manager.with_status_codes(true), Request::new(GetUserPermissionMaskRequest { user_id: "missing".to_string() }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[test]
+fn temporary_grants_lapse_at_their_expiry() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ"]);
+    let (names, expires_at) = manager.grant_temporary_permission(&user.id, "WRITE", Duration::from_secs(3600)).unwrap();
+    assert_eq!(names, common::names(&["READ", "WRITE"]));
+    assert!(manager.check_permissions(&user.id, &common::names(&["WRITE"]), Default::default()).unwrap().0);
+    assert!(manager.grant_temporary_permission(&user.id, "WRITE", Duration::ZERO).is_err());
+
+    // A grant that expired while the service was down is stored but not held
+    let mut expired = common::user("expired@example.com", 1 | 2);
+    expired.expiries.insert(2, 1);
+    let mut live = common::user("live@example.com", 1 | 2);
+    live.expiries.insert(2, expires_at);
+    let manager = common::load_with_users(vec![expired.clone(), live.clone()]);
+    assert_eq!(manager.check_permissions(&expired.id, &common::names(&["READ", "WRITE"]), Default::default()).unwrap(), (false, common::names(&["WRITE"])));
+    assert!(manager.check_permissions(&live.id, &common::names(&["READ", "WRITE"]), Default::default()).unwrap().0);
+}
+
+#[tokio::test]
+async fn reset_revokes_every_permission() {
+    let manager = common::manager().with_status_codes(true);
+    let user = common::create_user(&manager, "user@example.com", &["READ", "WRITE", "DELETE"]);
+    manager.grant_temporary_permission(&user.id, "EXECUTE", Duration::from_secs(3600)).unwrap();
+    let reset = |user_id: &str| IamService::reset_user_permissions(&manager, Request::new(ResetUserPermissionsRequest { user_id: user_id.to_string() }));
+
+    let response = reset(&user.id).await.unwrap().into_inner();
+    assert!(response.success);
+    assert!(response.permissions.is_empty());
+
+    let all = common::names(&["READ", "WRITE", "EXECUTE", "DELETE"]);
+    assert!(!manager.check_permissions(&user.id, &all, MatchMode::Any).unwrap().0);
+    assert!(manager.get_user(&user.id).unwrap().expiries.is_empty());
+    assert_eq!(manager.get_audit_log(Some(&user.id), 1).unwrap()[0].0.action, AuditAction::Reset);
+
+    assert_eq!(reset("missing").await.unwrap_err().code(), Code::NotFound);
+}
@@ -0,0 +1,64 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::net::TcpListener;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Channel, Server};
+
+use bitflags_iam::iam::iam_service_client::IamServiceClient;
+use bitflags_iam::iam::iam_service_server::IamServiceServer;
+use bitflags_iam::iam::CreateUserRequest;
+use bitflags_iam::server::RequestLogger;
+use bitflags_iam::IamManager;
+
+// Keeps every record so the test can inspect what would have been printed
+struct CapturingLogger {
+    lines: Mutex<Vec<String>>,
+}
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.lines.lock().unwrap().push(format!("{} {}", record.level(), record.args()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger { lines: Mutex::new(Vec::new()) };
+
+#[tokio::test]
+async fn logged_create_user_has_no_password() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+    let manager = Arc::new(IamManager::new().with_bcrypt_cost(4).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+    let router = Server::builder().add_service(RequestLogger::new(IamServiceServer::from_arc(Arc::clone(&manager))));
+    tokio::spawn(router.serve_with_incoming(incoming));
+    let channel = Channel::from_shared(format!("http://{}", addr)).unwrap().connect().await.unwrap();
+
+    let response = IamServiceClient::new(channel)
+        .create_user(CreateUserRequest {
+            name: "Test User".to_string(),
+            email: "user@example.com".to_string(),
+            password: "s3cret-passw0rd".to_string(),
+            permissions: vec!["READ".to_string()],
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.success);
+
+    let lines = LOGGER.lines.lock().unwrap();
+    let line = lines.iter().find(|line| line.contains("CreateUser")).expect("CreateUser payload logged");
+    assert!(line.starts_with("DEBUG"));
+    assert!(line.contains("user@example.com"));
+    assert!(line.contains("***"));
+    assert!(!line.contains("s3cret-passw0rd"));
+    let hash = &manager.all_users().unwrap()[0].0.password_hash;
+    assert!(lines.iter().all(|line| !line.contains("s3cret-passw0rd") && !line.contains(hash.as_str())));
+}
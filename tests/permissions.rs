@@ -0,0 +1,335 @@
+mod common;
+
+use bitflags_iam::iam::iam_service_server::IamService;
+use bitflags_iam::iam::ListPermissionsRequest;
+use bitflags_iam::{IamError, IamManager, Permission};
+use tonic::Request;
+
+#[test]
+fn labels_round_trip_without_affecting_resolution() {
+    let manager = common::manager();
+    manager.add_permission("AUDIT".to_string(), "Audit logs".to_string(), String::new(), None, None).unwrap();
+    let user = common::create_user(&manager, "user@example.com", &[]);
+
+    let label_of = |name: &str| manager.list_permissions().unwrap().into_iter().find(|p| p.name == name).unwrap().label;
+    assert_eq!(label_of("AUDIT"), "Audit logs");
+    assert_eq!(manager.set_permission_label("AUDIT", "Audit trail".to_string()).unwrap().label, "Audit trail");
+    assert_eq!(label_of("AUDIT"), "Audit trail");
+
+    manager.update_user_permissions(&user.id, common::names(&["AUDIT"])).unwrap();
+    assert_eq!(manager.get_user_with_permission_names(&user.id).unwrap().unwrap().1, common::names(&["AUDIT"]));
+    assert!(manager.update_user_permissions(&user.id, common::names(&["Audit trail"])).is_err());
+    assert!(manager.set_permission_label("MISSING", String::new()).is_err());
+}
+
+#[test]
+fn max_permissions_caps_the_registry() {
+    let manager = common::manager().with_max_permissions(6);
+    common::add_permission(&manager, "ADMIN");
+    common::add_permission(&manager, "AUDIT");
+
+    let rejected = manager.add_permission("DEPLOY".to_string(), String::new(), String::new(), None, None);
+    assert_eq!(rejected.unwrap_err(), IamError::ResourceExhausted("Permission registry is full (maximum 6 permissions)".to_string()));
+    assert_eq!(manager.list_permissions().unwrap().len(), 6);
+
+    manager.remove_permission("AUDIT").unwrap();
+    common::add_permission(&manager, "DEPLOY");
+}
+
+#[test]
+fn applying_a_template_twice_is_idempotent() {
+    let template = vec![Permission::new("BILLING_READ".to_string(), 16), Permission::new("BILLING_WRITE".to_string(), 64)];
+    let manager = common::manager().with_permission_template("billing".to_string(), template);
+
+    let (added, skipped) = manager.apply_permission_template("billing").unwrap();
+    let added: Vec<(String, u64)> = added.into_iter().map(|p| (p.name, p.value)).collect();
+    assert_eq!(added, vec![("BILLING_READ".to_string(), 16), ("BILLING_WRITE".to_string(), 64)]);
+    assert!(skipped.is_empty());
+
+    let (added, skipped) = manager.apply_permission_template("billing").unwrap();
+    assert!(added.is_empty());
+    assert_eq!(skipped, common::names(&["BILLING_READ", "BILLING_WRITE"]));
+    assert_eq!(manager.list_permissions().unwrap().len(), 6);
+    assert_eq!(manager.apply_permission_template("missing").unwrap_err(), IamError::NotFound("Permission template 'missing' not found".to_string()));
+}
+
+#[test]
+fn template_values_must_be_free_single_bits() {
+    let manager = common::manager()
+        .with_permission_template("clash".to_string(), vec![Permission::new("AUDIT".to_string(), 16), Permission::new("ADMIN".to_string(), 2)])
+        .with_permission_template("multi".to_string(), vec![Permission::new("ADMIN".to_string(), 48)]);
+
+    assert_eq!(manager.apply_permission_template("clash").unwrap_err(), IamError::AlreadyExists("Template permission 'ADMIN' value 2 is already used by 'WRITE'".to_string()));
+    assert_eq!(manager.apply_permission_template("multi").unwrap_err(), IamError::InvalidArgument("Template permission 'ADMIN' has value 48 which is not a single bit".to_string()));
+    assert_eq!(manager.list_permissions().unwrap().len(), 4);
+}
+
+#[test]
+fn permission_by_value_resolves_registered_bits_only() {
+    let manager = common::manager();
+
+    assert_eq!(manager.get_permission_by_value(4).unwrap().name, "EXECUTE");
+    assert_eq!(manager.get_permission_by_value(32).unwrap_err(), IamError::NotFound("Permission not found".to_string()));
+    assert_eq!(manager.get_permission_by_value(3).unwrap_err(), IamError::InvalidArgument("Value 3 is not a single permission bit".to_string()));
+    assert_eq!(manager.get_permission_by_value(0).unwrap_err(), IamError::InvalidArgument("Value 0 is not a single permission bit".to_string()));
+}
+
+#[test]
+fn reserved_bits_are_skipped_until_claimed() {
+    let manager = common::manager();
+    manager.remove_permission("DELETE").unwrap();
+
+    assert_eq!(manager.reserve_bits(&[8]).unwrap(), vec![8]);
+    assert_eq!(common::add_permission(&manager, "ADMIN").value, 16);
+    assert_eq!(common::add_permission(&manager, "AUDIT").value, 32);
+
+    let claimed = manager.add_permission("DEPLOY".to_string(), String::new(), String::new(), None, Some(8)).unwrap();
+    assert_eq!(claimed.value, 8);
+    assert_eq!(manager.reserve_bits(&[]).unwrap(), Vec::<u64>::new());
+    assert_eq!(manager.reserve_bits(&[1]).unwrap_err(), IamError::AlreadyExists("Value 1 is already used by 'READ'".to_string()));
+    assert_eq!(manager.reserve_bits(&[6]).unwrap_err(), IamError::InvalidArgument("Value 6 is not a single permission bit".to_string()));
+}
+
+#[test]
+fn search_permissions_by_prefix() {
+    let manager = common::manager();
+    for name in ["BILLING_READ", "BILLING_WRITE", "BILLING_ADMIN"] {
+        common::add_permission(&manager, name);
+    }
+
+    let names = |found: Vec<Permission>| found.into_iter().map(|p| p.name).collect::<Vec<_>>();
+    assert_eq!(names(manager.search_permissions("billing_", 0).unwrap()), common::names(&["BILLING_ADMIN", "BILLING_READ", "BILLING_WRITE"]));
+    assert_eq!(names(manager.search_permissions("Bill", 2).unwrap()), common::names(&["BILLING_ADMIN", "BILLING_READ"]));
+    assert!(manager.search_permissions("INVOICE", 10).unwrap().is_empty());
+}
+
+#[test]
+fn describe_permission_counts_holders() {
+    let manager = common::manager();
+    common::create_user(&manager, "first@example.com", &["READ", "WRITE"]);
+    common::create_user(&manager, "second@example.com", &["READ"]);
+
+    let (permission, holders, _, _) = manager.describe_permission("READ").unwrap();
+    assert_eq!((permission.value, holders), (1, 2));
+    assert_eq!(manager.describe_permission("DELETE").unwrap().1, 0);
+    assert_eq!(manager.describe_permission("MISSING").unwrap_err(), IamError::NotFound("Permission not found".to_string()));
+}
+
+#[test]
+fn batch_rename_is_all_or_nothing() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ", "WRITE"]);
+    let renames = |pairs: &[(&str, &str)]| pairs.iter().map(|(old, new)| (old.to_string(), new.to_string())).collect::<Vec<_>>();
+
+    let rejected = manager.batch_rename_permissions(&renames(&[("READ", "VIEW"), ("WRITE", "EXECUTE")]));
+    assert_eq!(rejected.unwrap_err(), IamError::AlreadyExists("Cannot rename 'WRITE' to 'EXECUTE': permission already exists".to_string()));
+    assert_eq!(manager.batch_rename_permissions(&renames(&[("READ", "VIEW"), ("MISSING", "OTHER")])).unwrap_err(), IamError::NotFound("Permission 'MISSING' not found".to_string()));
+    assert_eq!(manager.get_user_with_permission_names(&user.id).unwrap().unwrap().1, common::names(&["READ", "WRITE"]));
+
+    // Swapping two names is allowed since both are renamed away
+    manager.batch_rename_permissions(&renames(&[("READ", "WRITE"), ("WRITE", "READ")])).unwrap();
+    let names = manager.list_permissions().unwrap().into_iter().map(|p| (p.name, p.value)).collect::<std::collections::HashMap<_, _>>();
+    assert_eq!((names["WRITE"], names["READ"]), (1, 2));
+}
+
+#[test]
+fn add_permission_reuses_the_bit_of_a_removed_permission() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ", "WRITE"]);
+
+    manager.remove_permission("WRITE").unwrap();
+    assert_eq!(manager.get_user(&user.id).unwrap().permissions, 1);
+
+    let admin = common::add_permission(&manager, "ADMIN");
+    assert_eq!(admin.value, 2);
+    // The user lost WRITE's bit, so the reused value grants nothing
+    let (allowed, _) = manager.check_permissions(&user.id, &common::names(&["ADMIN"]), Default::default()).unwrap();
+    assert!(!allowed);
+}
+
+#[tokio::test]
+async fn descriptions_and_creation_times_are_kept() {
+    let manager = common::manager();
+    let added = manager.add_permission("AUDIT".to_string(), String::new(), "Read audit logs".to_string(), Some(3), None).unwrap();
+    assert!(added.created_at > 0);
+
+    let listed = manager.list_permissions().unwrap().into_iter().find(|p| p.name == "AUDIT").unwrap();
+    assert_eq!(listed.description, "Read audit logs");
+    assert_eq!(listed.created_at, added.created_at);
+    assert_eq!(common::add_permission(&manager, "DEPLOY").description, "");
+
+    // The RPC carries both fields
+    let response = IamService::list_permissions(&manager, Request::new(ListPermissionsRequest {})).await.unwrap().into_inner();
+    let proto = response.permissions.into_iter().find(|p| p.name == "AUDIT").unwrap();
+    assert_eq!((proto.description.as_str(), proto.created_at), ("Read audit logs", added.created_at));
+}
+
+#[test]
+fn seeded_permissions_get_distinct_bits() {
+    let manager = IamManager::with_permissions(vec![
+        ("view".to_string(), "View pages".to_string()),
+        ("EDIT".to_string(), String::new()),
+        ("VIEW".to_string(), "Duplicate, skipped".to_string()),
+        ("PUBLISH".to_string(), String::new()),
+    ]);
+
+    let mut seeded: Vec<(String, u64, String)> = manager.list_permissions().unwrap().into_iter().map(|p| (p.name, p.value, p.description)).collect();
+    seeded.sort_by_key(|(_, value, _)| *value);
+    assert_eq!(seeded, vec![
+        ("VIEW".to_string(), 1, "View pages".to_string()),
+        ("EDIT".to_string(), 2, String::new()),
+        ("PUBLISH".to_string(), 4, String::new()),
+    ]);
+    assert_eq!(common::manager().list_permissions().unwrap().len(), 4);
+}
+
+#[test]
+fn permission_names_match_case_insensitively() {
+    let manager = common::manager();
+    assert_eq!(manager.add_permission("read".to_string(), String::new(), String::new(), None, None).unwrap_err(), IamError::AlreadyExists("Permission already exists".to_string()));
+    assert_eq!(common::add_permission(&manager, "Admin").name, "ADMIN");
+
+    let user = common::create_user(&manager, "user@example.com", &["read", "Write"]);
+    assert_eq!(user.permissions, 3);
+    assert!(manager.check_permissions(&user.id, &common::names(&["READ", "write"]), Default::default()).unwrap().0);
+    manager.grant_permission(&user.id, "aDmIn").unwrap();
+    assert!(manager.check_permissions(&user.id, &common::names(&["admin"]), Default::default()).unwrap().0);
+}
+
+#[test]
+fn composite_values_never_block_bit_allocation() {
+    let manager = common::manager();
+    // READ|WRITE = 3 and EXECUTE|DELETE = 12 are not single bits, so 16 is still the next free bit
+    manager.add_composite_permission("READ_WRITE".to_string(), common::names(&["READ", "WRITE"]), String::new(), String::new()).unwrap();
+    manager.add_composite_permission("RUN".to_string(), common::names(&["EXECUTE", "DELETE"]), String::new(), String::new()).unwrap();
+
+    assert_eq!(common::add_permission(&manager, "ADMIN").value, 16);
+    assert_eq!(bitflags_iam::find_next_available_bit(&[1, 3, 2, 12]).unwrap(), 4);
+}
+
+#[test]
+fn renamed_permission_shows_in_user_lists() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ", "WRITE"]);
+
+    let renamed = manager.rename_permission("write", "edit").unwrap();
+    assert_eq!((renamed.name.as_str(), renamed.value), ("EDIT", 2));
+
+    assert_eq!(manager.get_user_with_permission_names(&user.id).unwrap().unwrap().1, common::names(&["EDIT", "READ"]));
+    assert!(manager.check_permissions(&user.id, &common::names(&["EDIT"]), Default::default()).unwrap().0);
+    assert_eq!(manager.check_permissions(&user.id, &common::names(&["WRITE"]), Default::default()).unwrap_err(), IamError::NotFound("Permission 'WRITE' not found".to_string()));
+}
+
+#[test]
+fn exported_catalog_imports_with_the_same_values() {
+    let source = common::manager();
+    source.add_permission("BILLING".to_string(), String::new(), "Invoices".to_string(), None, Some(1 << 20)).unwrap();
+    let target = common::manager();
+
+    let (imported, skipped) = target.import_permissions(source.export_permissions().unwrap()).unwrap();
+    assert_eq!(imported.iter().map(|p| (p.name.as_str(), p.value)).collect::<Vec<_>>(), vec![("BILLING", 1 << 20)]);
+    assert_eq!(skipped, common::names(&["DELETE", "EXECUTE", "READ", "WRITE"]));
+    let catalog = |manager: &IamManager| -> Vec<(String, u64, String)> {
+        manager.export_permissions().unwrap().into_iter().map(|p| (p.name, p.value, p.description)).collect()
+    };
+    assert_eq!(catalog(&target), catalog(&source));
+
+    // A value already taken under another name is reported and nothing is imported
+    let clashing = common::manager();
+    clashing.add_permission("AUDIT".to_string(), String::new(), String::new(), None, Some(1 << 20)).unwrap();
+    let IamError::Conflicts(conflicts) = clashing.import_permissions(source.export_permissions().unwrap()).unwrap_err() else {
+        panic!("expected conflicts");
+    };
+    assert_eq!(conflicts, vec![format!("Value {} is used by both 'AUDIT' and 'BILLING'", 1u64 << 20)]);
+    assert_eq!(clashing.get_permission_by_value(1 << 20).unwrap().name, "AUDIT");
+}
+
+#[test]
+fn requested_values_must_be_free_single_bits() {
+    let manager = common::manager();
+    let add = |name: &str, value: Option<u64>| manager.add_permission(name.to_string(), String::new(), String::new(), None, value);
+
+    for value in [0, 3, 6, u64::MAX] {
+        assert_eq!(add("ADMIN", Some(value)).unwrap_err(), IamError::InvalidArgument(format!("Value {} is not a single permission bit", value)));
+    }
+    assert_eq!(add("ADMIN", Some(2)).unwrap_err(), IamError::AlreadyExists("Value 2 is already used by 'WRITE'".to_string()));
+    assert_eq!(manager.list_permissions().unwrap().len(), 4);
+
+    assert_eq!(add("ADMIN", Some(1 << 63)).unwrap().value, 1 << 63);
+    assert_eq!(add("AUDIT", None).unwrap().value, 16);
+}
+
+#[test]
+fn import_rejects_entries_sharing_a_value() {
+    let manager = common::manager();
+    let incoming = vec![
+        Permission::new("ADMIN".to_string(), 16),
+        Permission::new("AUDIT".to_string(), 16),
+        Permission::new("DEPLOY".to_string(), 32),
+    ];
+
+    let IamError::Conflicts(conflicts) = manager.import_permissions(incoming).unwrap_err() else {
+        panic!("expected conflicts");
+    };
+    assert_eq!(conflicts, vec!["Value 16 is used by both 'ADMIN' and 'AUDIT'".to_string()]);
+    assert_eq!(manager.list_permissions().unwrap().len(), 4);
+}
+
+#[test]
+fn composite_is_held_only_with_all_of_its_bits() {
+    let manager = common::manager();
+    manager.add_composite_permission("FULL_ACCESS".to_string(), common::names(&["READ", "WRITE"]), String::new(), String::new()).unwrap();
+    let full = common::create_user(&manager, "full@example.com", &["READ", "WRITE"]);
+    let partial = common::create_user(&manager, "partial@example.com", &["READ"]);
+
+    let (_, holders, _, _) = manager.describe_permission("FULL_ACCESS").unwrap();
+    assert_eq!(holders, 1);
+    assert!(manager.check_permissions(&full.id, &common::names(&["FULL_ACCESS"]), Default::default()).unwrap().0);
+    assert!(!manager.check_permissions(&partial.id, &common::names(&["FULL_ACCESS"]), Default::default()).unwrap().0);
+    assert!(!manager.get_user_with_permission_names(&partial.id).unwrap().unwrap().1.contains(&"FULL_ACCESS".to_string()));
+}
+
+#[test]
+fn composites_count_towards_max_permissions() {
+    let manager = common::manager().with_max_permissions(5);
+    manager.add_composite_permission("READ_WRITE".to_string(), common::names(&["READ", "WRITE"]), String::new(), String::new()).unwrap();
+
+    let rejected = manager.add_composite_permission("RUN".to_string(), common::names(&["EXECUTE", "DELETE"]), String::new(), String::new());
+    assert_eq!(rejected.unwrap_err(), IamError::ResourceExhausted("Permission registry is full (maximum 5 permissions)".to_string()));
+    assert_eq!(manager.list_permissions().unwrap().len(), 5);
+}
+
+#[test]
+fn describe_permission_lists_holders_and_what_references_it() {
+    let manager = common::manager();
+    manager.add_composite_permission("READ_WRITE".to_string(), common::names(&["READ", "WRITE"]), String::new(), String::new()).unwrap();
+    manager.add_composite_permission("RUN".to_string(), common::names(&["EXECUTE", "DELETE"]), String::new(), String::new()).unwrap();
+    manager.create_role("VIEWER".to_string(), &common::names(&["READ"])).unwrap();
+    manager.create_role("OPS".to_string(), &common::names(&["EXECUTE"])).unwrap();
+    common::create_user(&manager, "first@example.com", &["READ"]);
+    common::create_user(&manager, "second@example.com", &["READ", "WRITE"]);
+    common::create_user(&manager, "third@example.com", &["WRITE"]);
+
+    let (permission, holders, composites, roles) = manager.describe_permission("read").unwrap();
+    assert_eq!(permission.value, 1);
+    assert_eq!(holders, 2);
+    assert_eq!(composites, common::names(&["READ_WRITE"]));
+    assert_eq!(roles, common::names(&["VIEWER"]));
+
+    let (_, _, composites, roles) = manager.describe_permission("READ_WRITE").unwrap();
+    assert!(composites.is_empty());
+    assert!(roles.is_empty());
+}
+
+#[test]
+fn invalid_permission_names_are_rejected() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ"]);
+
+    for name in ["", "   ", "AUDIT LOG", "AUDIT*"] {
+        assert!(matches!(manager.add_permission(name.to_string(), String::new(), String::new(), None, None), Err(IamError::InvalidArgument(_))), "{:?}", name);
+        assert!(matches!(manager.rename_permission("READ", name), Err(IamError::InvalidArgument(_))), "{:?}", name);
+    }
+    assert_eq!(common::add_permission(&manager, " audit ").name, "AUDIT");
+    assert_eq!(manager.get_user_with_permission_names(&user.id).unwrap().unwrap().1, common::names(&["READ"]));
+}
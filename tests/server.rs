@@ -0,0 +1,159 @@
+mod common;
+
+use std::sync::Arc;
+
+use tokio::net::TcpListener;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Server};
+use tonic::codec::ProstCodec;
+use tonic::{Code, Request};
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+
+use bitflags_iam::iam::iam_service_server::IamServiceServer;
+use bitflags_iam::iam::iam_service_client::IamServiceClient;
+use bitflags_iam::iam::{AddPermissionRequest, CheckPermissionsRequest, ListPermissionsRequest, ListUsersRequest};
+use bitflags_iam::server::{health_service, tls_config, ApiKeyInterceptor, OPEN_RPCS};
+use bitflags_iam::IamManager;
+
+// Serves the manager with the health service on a free local port. With admin permissions,
+// RPCs outside OPEN_RPCS require an API key as they do in the server binary.
+async fn serve_with_keys(manager: IamManager, admin_permissions: Option<Vec<String>>) -> Channel {
+    let manager = Arc::new(manager);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+
+    let iam_service = IamServiceServer::from_arc(Arc::clone(&manager));
+    let router = Server::builder().add_service(health_service(&manager).await);
+    #[cfg(feature = "reflection")]
+    let router = router.add_service(bitflags_iam::server::reflection_service().unwrap());
+    let router = match admin_permissions {
+        Some(admin_permissions) => router.add_service(ApiKeyInterceptor::new(iam_service, manager, admin_permissions)),
+        None => router.add_service(iam_service),
+    };
+    tokio::spawn(router.serve_with_incoming(incoming));
+
+    Channel::from_shared(format!("http://{}", addr)).unwrap().connect().await.unwrap()
+}
+
+async fn serve(manager: IamManager) -> Channel {
+    serve_with_keys(manager, None).await
+}
+
+fn with_key<T>(message: T, key: Option<&str>) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Some(key) = key {
+        request.metadata_mut().insert("authorization", format!("Bearer {}", key).parse().unwrap());
+    }
+    request
+}
+
+async fn health_status(channel: Channel) -> ServingStatus {
+    let response = HealthClient::new(channel)
+        .check(HealthCheckRequest { service: "iam.IamService".to_string() })
+        .await
+        .unwrap();
+    response.into_inner().status()
+}
+
+#[tokio::test]
+async fn health_check_reports_serving() {
+    let channel = serve(common::manager()).await;
+    assert_eq!(health_status(channel).await, ServingStatus::Serving);
+}
+
+#[tokio::test]
+async fn health_check_reports_an_unavailable_manager() {
+    let channel = serve(IamManager::unavailable("Store failed to load")).await;
+    assert_eq!(health_status(channel).await, ServingStatus::NotServing);
+}
+
+#[tokio::test]
+async fn management_rpcs_require_an_admin_key() {
+    let manager = common::manager();
+    common::add_permission(&manager, "ADMIN");
+    let admin = common::create_user(&manager, "admin@example.com", &["ADMIN"]);
+    let reader = common::create_user(&manager, "reader@example.com", &["READ"]);
+    manager.add_api_key("admin-key".to_string(), &admin.id).unwrap();
+    manager.add_api_key("reader-key".to_string(), &reader.id).unwrap();
+    let mut client = IamServiceClient::new(serve_with_keys(manager, Some(common::names(&["ADMIN"]))).await);
+    let add = |key| with_key(AddPermissionRequest { permission_name: "AUDIT".to_string(), ..Default::default() }, key);
+
+    assert_eq!(client.add_permission(add(None)).await.unwrap_err().code(), Code::Unauthenticated);
+    assert_eq!(client.add_permission(add(Some("unknown-key"))).await.unwrap_err().code(), Code::Unauthenticated);
+    assert_eq!(client.add_permission(add(Some("reader-key"))).await.unwrap_err().code(), Code::PermissionDenied);
+    assert_eq!(client.list_users(with_key(ListUsersRequest::default(), None)).await.unwrap_err().code(), Code::Unauthenticated);
+
+    assert!(client.add_permission(add(Some("admin-key"))).await.unwrap().into_inner().success);
+    assert_eq!(client.list_users(with_key(ListUsersRequest::default(), Some("admin-key"))).await.unwrap().into_inner().users.len(), 2);
+    assert_eq!(client.list_permissions(with_key(ListPermissionsRequest::default(), None)).await.unwrap_err().code(), Code::Unauthenticated);
+    let permissions = client.list_permissions(with_key(ListPermissionsRequest::default(), Some("admin-key"))).await.unwrap().into_inner();
+    assert!(permissions.permissions.iter().any(|p| p.name == "AUDIT"));
+    let check = CheckPermissionsRequest { user_id: reader.id.clone(), required_permissions: common::names(&["READ"]), ..Default::default() };
+    assert!(client.check_permissions(with_key(check, None)).await.unwrap().into_inner().has_permissions);
+}
+
+#[tokio::test]
+async fn every_rpc_outside_the_open_list_requires_a_key() {
+    let proto = std::fs::read_to_string(format!("{}/proto/iam.proto", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let methods: Vec<&str> = proto.lines().filter_map(|line| line.trim().strip_prefix("rpc ")?.split('(').next()).collect();
+    assert!(methods.len() > 40);
+    let manager = common::manager();
+    common::add_permission(&manager, "ADMIN");
+    let mut grpc = tonic::client::Grpc::new(serve_with_keys(manager, Some(common::names(&["ADMIN"]))).await);
+
+    // An empty message decodes as the default of any request type
+    for method in methods {
+        grpc.ready().await.unwrap();
+        let path = format!("/iam.IamService/{}", method).parse().unwrap();
+        let result = grpc.unary::<(), (), _>(Request::new(()), path, ProstCodec::default()).await;
+        let rejected = matches!(&result, Err(status) if status.code() == Code::Unauthenticated && status.message() == "Missing API key");
+        assert_eq!(rejected, !OPEN_RPCS.contains(&method), "{}", method);
+    }
+}
+
+#[cfg(feature = "reflection")]
+#[tokio::test]
+async fn reflection_lists_the_iam_service() {
+    use tonic_reflection::pb::v1::server_reflection_client::ServerReflectionClient;
+    use tonic_reflection::pb::v1::server_reflection_request::MessageRequest;
+    use tonic_reflection::pb::v1::server_reflection_response::MessageResponse;
+    use tonic_reflection::pb::v1::ServerReflectionRequest;
+
+    let mut client = ServerReflectionClient::new(serve(common::manager()).await);
+    let request = ServerReflectionRequest { host: String::new(), message_request: Some(MessageRequest::ListServices(String::new())) };
+    let mut responses = client.server_reflection_info(tokio_stream::iter(vec![request])).await.unwrap().into_inner();
+
+    let Some(MessageResponse::ListServicesResponse(list)) = responses.message().await.unwrap().unwrap().message_response else {
+        panic!("expected a service list");
+    };
+    assert!(list.service.iter().any(|service| service.name == "iam.IamService"));
+}
+
+#[tokio::test]
+async fn list_permissions_over_tls() {
+    let fixture = |name: &str| format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+    let router = Server::builder()
+        .tls_config(tls_config(&fixture("server.pem"), &fixture("server.key")).unwrap())
+        .unwrap()
+        .add_service(IamServiceServer::new(common::manager()));
+    tokio::spawn(router.serve_with_incoming(incoming));
+
+    let ca = Certificate::from_pem(std::fs::read_to_string(fixture("ca.pem")).unwrap());
+    let channel = Channel::from_shared(format!("https://localhost:{}", addr.port()))
+        .unwrap()
+        .tls_config(ClientTlsConfig::new().ca_certificate(ca).domain_name("localhost"))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let response = IamServiceClient::new(channel).list_permissions(ListPermissionsRequest::default()).await.unwrap().into_inner();
+    assert_eq!(response.permissions.len(), common::manager().list_permissions().unwrap().len());
+    assert!(tls_config(&fixture("missing.pem"), &fixture("server.key")).unwrap_err().starts_with("Failed to read TLS certificate"));
+}
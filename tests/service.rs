@@ -0,0 +1,128 @@
+mod common;
+
+use bitflags_iam::iam::iam_service_server::IamService;
+use bitflags_iam::iam::{AddPermissionRequest, CheckPermissionsRequest, CreateUserRequest, GetUserRequest, GrantPermissionRequest, MatchMode as ProtoMatchMode};
+use bitflags_iam::{IamError, MatchMode};
+use tonic::{Code, Request};
+
+#[tokio::test]
+async fn get_user_reports_missing_user_as_not_found() {
+    let manager = common::manager().with_status_codes(true);
+
+    let status = IamService::get_user(&manager, Request::new(GetUserRequest { user_id: "missing".to_string() }))
+        .await
+        .unwrap_err();
+
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(status.message(), "User not found");
+}
+
+#[tokio::test]
+async fn failures_keep_success_false_without_status_codes() {
+    let manager = common::manager();
+
+    let response = IamService::grant_permission(&manager, Request::new(GrantPermissionRequest {
+        user_id: "missing".to_string(),
+        permission_name: "READ".to_string(),
+    }))
+    .await
+    .unwrap()
+    .into_inner();
+
+    assert!(!response.success);
+    assert_eq!(response.message, "User not found");
+}
+
+#[tokio::test]
+async fn error_kind_decides_the_status_code() {
+    let manager = common::manager().with_status_codes(true);
+    let add = |name: &str, requested_value: Option<u64>| AddPermissionRequest {
+        permission_name: name.to_string(),
+        requested_value,
+        ..Default::default()
+    };
+
+    let duplicate = IamService::add_permission(&manager, Request::new(add("READ", None))).await.unwrap_err();
+    assert_eq!(duplicate.code(), Code::AlreadyExists);
+
+    let not_a_bit = IamService::add_permission(&manager, Request::new(add("ADMIN", Some(3)))).await.unwrap_err();
+    assert_eq!(not_a_bit.code(), Code::InvalidArgument);
+}
+
+#[test]
+fn manager_errors_are_typed() {
+    let manager = common::manager();
+
+    assert_eq!(manager.grant_permission("missing", "READ").unwrap_err(), IamError::NotFound("User not found".to_string()));
+    assert!(matches!(manager.check_permissions_by_mask("missing", 1), Err(IamError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn rpcs_match_the_library_api() {
+    let manager = common::manager();
+
+    let created = IamService::create_user(&manager, Request::new(CreateUserRequest {
+        name: "Test User".to_string(),
+        email: "user@example.com".to_string(),
+        password: "password123".to_string(),
+        permissions: common::names(&["write", "READ"]),
+    }))
+    .await
+    .unwrap()
+    .into_inner();
+    assert!(created.success);
+    assert_eq!(created.message, "User created successfully");
+    let user = created.user.unwrap();
+    assert_eq!(user.permissions, common::names(&["READ", "WRITE"]));
+    assert_eq!(manager.get_user(&user.id).unwrap().permissions, 3);
+
+    let granted = IamService::grant_permission(&manager, Request::new(GrantPermissionRequest {
+        user_id: user.id.clone(),
+        permission_name: "DELETE".to_string(),
+    }))
+    .await
+    .unwrap()
+    .into_inner();
+    assert!(granted.success);
+    assert_eq!(granted.permissions, manager.get_user_with_permission_names(&user.id).unwrap().unwrap().1);
+
+    for (required, mode) in [(&["READ", "EXECUTE"][..], ProtoMatchMode::All), (&["READ", "EXECUTE"], ProtoMatchMode::Any), (&[], ProtoMatchMode::All)] {
+        let checked = IamService::check_permissions(&manager, Request::new(CheckPermissionsRequest {
+            user_id: user.id.clone(),
+            required_permissions: common::names(required),
+            mode: mode as i32,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+        let library_mode = if mode == ProtoMatchMode::All { MatchMode::All } else { MatchMode::Any };
+        let (has_permissions, missing) = manager.check_permissions(&user.id, &common::names(required), library_mode).unwrap();
+        assert!(checked.success);
+        assert_eq!((checked.has_permissions, checked.missing_permissions), (has_permissions, missing));
+    }
+}
+
+#[tokio::test]
+async fn add_permission_reports_slot_exhaustion() {
+    let manager = common::manager();
+    for index in 4..64 {
+        common::add_permission(&manager, &format!("P_{}", index));
+    }
+    let registry = manager.list_permissions().unwrap();
+    assert_eq!(registry.len(), 64);
+    assert_eq!(registry.iter().fold(0, |mask, p| mask | p.value), u64::MAX);
+    let add = || Request::new(AddPermissionRequest { permission_name: "P_64".to_string(), ..Default::default() });
+
+    let response = IamService::add_permission(&manager, add()).await.unwrap().into_inner();
+    assert!(!response.success);
+    assert_eq!(response.message, "No more permission slots available");
+    assert!(response.permission.is_none());
+
+    let manager = manager.with_status_codes(true);
+    let status = IamService::add_permission(&manager, add()).await.unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+    assert_eq!(status.message(), "No more permission slots available");
+
+    let unchanged: Vec<(String, u64)> = manager.list_permissions().unwrap().into_iter().map(|p| (p.name, p.value)).collect();
+    assert_eq!(unchanged, registry.into_iter().map(|p| (p.name, p.value)).collect::<Vec<_>>());
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use bitflags_iam::iam::iam_service_server::IamService;
+use bitflags_iam::iam::{GetUserRequest, ListPermissionsRequest};
+use bitflags_iam::{IamError, IamManager, JsonFileStore, Store, StoreSnapshot};
+use tonic::{Code, Request};
+
+fn store_path() -> PathBuf {
+    std::env::temp_dir().join(format!("bitflags-iam-{}.json", uuid::Uuid::new_v4()))
+}
+
+// Accepts saves until told to fail
+#[derive(Clone, Default)]
+struct FlakyStore {
+    failing: Arc<AtomicBool>,
+}
+
+impl Store for FlakyStore {
+    fn load(&self) -> Result<StoreSnapshot, IamError> {
+        Ok(StoreSnapshot::default())
+    }
+
+    fn save(&self, _snapshot: &StoreSnapshot) -> Result<(), IamError> {
+        if self.failing.load(Ordering::SeqCst) {
+            Err(IamError::Internal("Failed to write store: disk full".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn state_survives_a_restart() {
+    let path = store_path();
+    let manager = common::manager().with_store(JsonFileStore::new(&path));
+    let user = common::create_user(&manager, "user@example.com", &["READ"]);
+    common::add_permission(&manager, "ADMIN");
+    manager.update_user_permissions(&user.id, common::names(&["READ", "ADMIN"])).unwrap();
+    manager.create_role("OPS".to_string(), &common::names(&["WRITE"])).unwrap();
+
+    let restarted = IamManager::load_from_path(&path).unwrap();
+    let (restored, names, _) = restarted.get_user_with_permission_names(&user.id).unwrap().unwrap();
+    assert_eq!(restored.email, "user@example.com");
+    assert_eq!(names, common::names(&["ADMIN", "READ"]));
+    assert_eq!(restarted.list_roles().unwrap()[0].1, common::names(&["WRITE"]));
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(!content.contains("password123"));
+    let mut tmp_path = path.clone().into_os_string();
+    tmp_path.push(".tmp");
+    assert!(!PathBuf::from(tmp_path).exists());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn store_saved_before_labels_and_holder_limits_loads() {
+    let path = store_path();
+    std::fs::write(&path, r#"{
+        "users": {},
+        "permissions": {
+            "READ": {"name": "READ", "value": 1},
+            "WRITE": {"name": "WRITE", "value": 2}
+        }
+    }"#).unwrap();
+
+    let manager = IamManager::load_from_path(&path).unwrap();
+    let read = manager.get_permission_by_value(1).unwrap();
+    assert_eq!(read.label, "");
+    assert_eq!(read.max_holders, None);
+    common::create_user(&manager, "user@example.com", &["READ", "WRITE"]);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn failed_save_rolls_the_mutation_back() {
+    let store = FlakyStore::default();
+    let manager = common::manager().with_store(store.clone());
+    let user = common::create_user(&manager, "user@example.com", &["READ"]);
+
+    store.failing.store(true, Ordering::SeqCst);
+    assert!(matches!(manager.grant_permission(&user.id, "WRITE"), Err(IamError::Internal(_))));
+    assert!(matches!(manager.add_permission("ADMIN".to_string(), String::new(), String::new(), None, None), Err(IamError::Internal(_))));
+
+    // Memory still matches what the store last accepted, and no audit entry was kept
+    assert_eq!(manager.get_user(&user.id).unwrap().permissions, 1);
+    assert!(manager.list_permissions().unwrap().iter().all(|p| p.name != "ADMIN"));
+    assert_eq!(manager.get_audit_log(Some(&user.id), 0).unwrap().len(), 1);
+
+    store.failing.store(false, Ordering::SeqCst);
+    manager.grant_permission(&user.id, "WRITE").unwrap();
+    assert_eq!(manager.get_user(&user.id).unwrap().permissions, 3);
+}
+
+#[test]
+fn unavailable_manager_rejects_writes_and_denies_checks() {
+    let manager = IamManager::unavailable("Store failed to load: invalid JSON");
+
+    let created = manager.create_user("Test User".to_string(), "user@example.com".to_string(), "password123".to_string(), vec![]);
+    assert!(matches!(created, Err(IamError::Unavailable(_))));
+    assert!(matches!(manager.add_permission("ADMIN".to_string(), String::new(), String::new(), None, None), Err(IamError::Unavailable(_))));
+    assert!(matches!(manager.check_permissions("any-user", &common::names(&["READ"]), bitflags_iam::MatchMode::All), Err(IamError::Unavailable(_))));
+    assert!(matches!(manager.check_permissions_by_mask("any-user", 1), Err(IamError::Unavailable(_))));
+    assert!(!manager.is_available());
+}
+
+#[tokio::test]
+async fn unavailable_manager_rejects_reads() {
+    let manager = IamManager::unavailable("Store failed to load: invalid JSON").with_status_codes(true);
+
+    assert!(matches!(manager.list_permissions(), Err(IamError::Unavailable(_))));
+    assert!(matches!(manager.list_users(0, ""), Err(IamError::Unavailable(_))));
+    assert!(matches!(manager.get_audit_log(None, 0), Err(IamError::Unavailable(_))));
+    let listed = IamService::list_permissions(&manager, Request::new(ListPermissionsRequest::default())).await.unwrap_err();
+    assert_eq!(listed.code(), Code::Unavailable);
+    let user = IamService::get_user(&manager, Request::new(GetUserRequest { user_id: "any-user".to_string() })).await.unwrap_err();
+    assert_eq!(user.code(), Code::Unavailable);
+}
+
+#[test]
+fn periodic_repair_clears_orphan_bits_and_saves() {
+    let path = store_path();
+    IamManager::new().save_to_path(&path).unwrap();
+    let store = JsonFileStore::new(&path);
+    let mut snapshot = store.load().unwrap();
+    let user = bitflags_iam::User::new_with_cost("Test User".to_string(), "user@example.com".to_string(), "password123".to_string(), 1 | (1 << 40), 4).unwrap();
+    snapshot.users.insert(user.id.clone(), user.clone());
+    store.save(&snapshot).unwrap();
+
+    let manager = Arc::new(IamManager::load_from_path(&path).unwrap());
+    assert_eq!(manager.check_integrity().unwrap().len(), 1);
+    manager.spawn_integrity_repair(std::time::Duration::from_millis(10));
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    // The saved store is the last thing a repair updates
+    while store.load().unwrap().users[&user.id].permissions != 1 {
+        assert!(std::time::Instant::now() < deadline, "orphan bit never repaired");
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(manager.check_integrity().unwrap().is_empty());
+    assert_eq!(manager.get_user(&user.id).unwrap().permissions, 1);
+    std::fs::remove_file(path).unwrap();
+}
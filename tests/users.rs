@@ -0,0 +1,373 @@
+mod common;
+
+use bitflags_iam::iam::iam_service_server::IamService;
+use bitflags_iam::iam::{ExportUserRequest, GetUserByEmailRequest, GetUserRequest, ListUsersRequest, StreamUsersRequest};
+use bitflags_iam::{IamError, IamManager, NewUser, PasswordPolicy, User};
+use tokio_stream::StreamExt;
+use tonic::{Code, Request};
+
+fn manager_with_users(count: usize) -> IamManager {
+    let manager = common::manager();
+    for index in 0..count {
+        common::create_user(&manager, &format!("user{}@example.com", index), &["READ"]);
+    }
+    manager
+}
+
+fn sorted_ids(manager: &IamManager) -> Vec<String> {
+    manager.all_users().unwrap().into_iter().map(|(user, _)| user.id).collect()
+}
+
+#[test]
+fn list_users_pages_cover_every_user_in_id_order() {
+    let manager = manager_with_users(10);
+
+    let mut ids = Vec::new();
+    let mut page_token = String::new();
+    loop {
+        let (page, next_page_token) = manager.list_users(3, &page_token).unwrap();
+        assert!(page.len() <= 3);
+        ids.extend(page.into_iter().map(|(user, _)| user.id));
+        if next_page_token.is_empty() {
+            break;
+        }
+        page_token = next_page_token;
+    }
+
+    assert_eq!(ids, sorted_ids(&manager));
+}
+
+#[test]
+fn user_chunks_yield_every_user_in_id_order() {
+    let manager = manager_with_users(10);
+
+    let ids: Vec<String> = manager.user_chunks(3).map(|user| user.unwrap().0.id).collect();
+    assert_eq!(ids, sorted_ids(&manager));
+    assert_eq!(manager.user_chunks(20).count(), 10);
+    assert_eq!(common::manager().user_chunks(3).count(), 0);
+}
+
+#[tokio::test]
+async fn stream_users_sends_every_user() {
+    let manager = manager_with_users(12);
+
+    let stream = IamService::stream_users(&manager, Request::new(StreamUsersRequest {})).await.unwrap().into_inner();
+    let users: Vec<_> = stream.collect::<Result<Vec<_>, _>>().await.unwrap();
+
+    assert_eq!(users.len(), manager.all_users().unwrap().len());
+    assert_eq!(users.iter().map(|user| user.id.clone()).collect::<Vec<_>>(), sorted_ids(&manager));
+    assert!(users.iter().all(|user| user.permissions == common::names(&["READ"])));
+}
+
+#[test]
+fn require_nonempty_permissions_rejects_permissionless_users() {
+    let manager = common::manager().with_require_nonempty_permissions(true);
+
+    let rejected = manager.create_user("Test User".to_string(), "empty@example.com".to_string(), "password123".to_string(), vec![]);
+    assert_eq!(rejected.unwrap_err(), IamError::InvalidArgument("User must be granted at least one permission".to_string()));
+    common::create_user(&manager, "reader@example.com", &["READ"]);
+
+    // Off by default
+    assert_eq!(common::create_user(&common::manager(), "empty@example.com", &[]).permissions, 0);
+}
+
+#[tokio::test]
+async fn get_user_flags_orphan_bits() {
+    let orphaned = common::user("orphaned@example.com", 1 | 64 | (1 << 40));
+    let clean = common::user("clean@example.com", 3);
+    let manager = common::load_with_users(vec![orphaned.clone(), clean.clone()]);
+
+    let get = |user_id: &str| IamService::get_user(&manager, Request::new(GetUserRequest { user_id: user_id.to_string() }));
+    let response = get(&orphaned.id).await.unwrap().into_inner();
+    assert!(response.has_orphan_bits);
+    assert_eq!(response.orphan_bit_values, vec![64, 1 << 40]);
+    assert_eq!(response.user.unwrap().permissions, common::names(&["READ"]));
+
+    let response = get(&clean.id).await.unwrap().into_inner();
+    assert!(!response.has_orphan_bits);
+    assert!(response.orphan_bit_values.is_empty());
+}
+
+#[test]
+fn id_prefix_is_prepended_and_resolved() {
+    let manager = common::manager().with_id_prefix("acme".to_string());
+    let user = common::create_user(&manager, "user@example.com", &["READ"]);
+
+    let (prefix, unique) = user.id.split_once(':').unwrap();
+    assert_eq!(prefix, "acme");
+    assert_eq!(unique.len(), 36);
+    assert_eq!(manager.get_user(&user.id).unwrap().email, "user@example.com");
+    assert!(manager.get_user(unique).is_none());
+    manager.update_user_permissions(&user.id, common::names(&["READ", "WRITE"])).unwrap();
+    assert!(manager.check_permissions(&user.id, &common::names(&["WRITE"]), Default::default()).unwrap().0);
+}
+
+#[test]
+fn describe_user_reports_the_password_cost() {
+    let cheap = common::manager();
+    let costly = IamManager::new().with_bcrypt_cost(5).unwrap();
+    let first = common::create_user(&cheap, "first@example.com", &["READ"]);
+    let second = common::create_user(&costly, "second@example.com", &[]);
+
+    let (described, permissions, cost) = cheap.describe_user(&first.id).unwrap();
+    assert_eq!((described.id, permissions, cost), (first.id, common::names(&["READ"]), 4));
+    assert_eq!(costly.describe_user(&second.id).unwrap().2, 5);
+    assert_eq!(bitflags_iam::bcrypt_cost("$2b$12$abcdefghijklmnopqrstuv").unwrap(), 12);
+    assert!(bitflags_iam::bcrypt_cost("plaintext").is_err());
+}
+
+#[tokio::test]
+async fn export_user_returns_everything_but_the_password_hash() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ"]);
+    manager.create_role("READER".to_string(), &common::names(&["READ"])).unwrap();
+    manager.create_role("EDITOR".to_string(), &common::names(&["READ", "WRITE"])).unwrap();
+    manager.grant_permission(&user.id, "EXECUTE").unwrap();
+    let export = |include_audit| IamService::export_user(&manager, Request::new(ExportUserRequest { user_id: user.id.clone(), include_audit }));
+
+    let response = export(true).await.unwrap().into_inner();
+    assert!(response.success);
+    let exported = response.user.clone().unwrap();
+    assert_eq!((exported.id.as_str(), exported.name.as_str(), exported.email.as_str()), (user.id.as_str(), "Test User", "user@example.com"));
+    assert_eq!(exported.permissions, common::names(&["EXECUTE", "READ"]));
+    assert_eq!(response.roles, common::names(&["READER"]));
+    let actions: Vec<&str> = response.audit_entries.iter().map(|entry| entry.action.as_str()).collect();
+    assert_eq!(actions, ["create", "grant"]);
+    assert!(!format!("{:?}", response).contains(&user.password_hash));
+
+    assert!(export(false).await.unwrap().into_inner().audit_entries.is_empty());
+    assert_eq!(manager.export_user("missing", true).unwrap_err(), IamError::NotFound("User not found".to_string()));
+}
+
+#[test]
+fn bcrypt_cost_must_be_within_range() {
+    for cost in [3, 32] {
+        let expected = IamError::InvalidArgument(format!("Invalid bcrypt cost {}: must be between 4 and 31", cost));
+        assert_eq!(IamManager::new().with_bcrypt_cost(cost).err().unwrap(), expected);
+        let user = User::new_with_cost("Test User".to_string(), "user@example.com".to_string(), "password123".to_string(), 0, cost);
+        assert_eq!(user.unwrap_err(), expected);
+    }
+    assert!(IamManager::new().with_bcrypt_cost(31).is_ok());
+}
+
+#[test]
+fn verify_credentials_accepts_only_the_right_password() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ", "WRITE"]);
+
+    let (verified, names) = manager.verify_credentials(" User@Example.com ", "password123").unwrap();
+    assert_eq!(verified.id, user.id);
+    assert_eq!(names, common::names(&["READ", "WRITE"]));
+
+    // Wrong password and unknown email fail the same way
+    assert_eq!(manager.verify_credentials("user@example.com", "wrong-password").unwrap_err(), IamError::Unauthenticated("Invalid credentials".to_string()));
+    assert_eq!(manager.verify_credentials("nobody@example.com", "password123").unwrap_err(), IamError::Unauthenticated("Invalid credentials".to_string()));
+}
+
+#[tokio::test]
+async fn deleted_user_is_gone() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "user@example.com", &["READ"]);
+
+    manager.delete_user(&user.id).unwrap();
+    let response = IamService::get_user(&manager, Request::new(GetUserRequest { user_id: user.id.clone() })).await.unwrap().into_inner();
+    assert!(!response.success);
+    assert_eq!(response.message, "User not found");
+    // A second delete tells the caller the user did not exist
+    assert_eq!(manager.delete_user(&user.id).unwrap_err(), IamError::NotFound("User not found".to_string()));
+    // The email can be registered again
+    common::create_user(&manager, "user@example.com", &[]);
+}
+
+#[tokio::test]
+async fn list_users_pages_by_id() {
+    let manager = manager_with_users(5);
+    let list = |page_token: String| IamService::list_users(&manager, Request::new(ListUsersRequest { page_size: 2, page_token }));
+
+    let mut ids = Vec::new();
+    let mut pages = 0;
+    let mut page_token = String::new();
+    loop {
+        let response = list(page_token).await.unwrap().into_inner();
+        assert!(response.success);
+        pages += 1;
+        ids.extend(response.users.into_iter().map(|user| user.id));
+        if response.next_page_token.is_empty() {
+            break;
+        }
+        page_token = response.next_page_token;
+    }
+
+    assert_eq!(pages, 3);
+    assert_eq!(ids, sorted_ids(&manager));
+    // Page size 0 uses the default
+    let all = IamService::list_users(&manager, Request::new(ListUsersRequest::default())).await.unwrap().into_inner();
+    assert_eq!((all.users.len(), all.next_page_token.as_str()), (5, ""));
+}
+
+#[test]
+fn duplicate_emails_are_rejected_whatever_their_case() {
+    let manager = common::manager();
+    common::create_user(&manager, "john@example.com", &[]);
+
+    let duplicate = manager.create_user("John".to_string(), "John@Example.com ".to_string(), "password123".to_string(), vec![]);
+    assert_eq!(duplicate.unwrap_err(), IamError::AlreadyExists("Email already registered".to_string()));
+    common::create_user(&manager, "jane@example.com", &[]);
+    assert_eq!(manager.all_users().unwrap().len(), 2);
+}
+
+#[test]
+fn a_shared_email_resolves_to_the_smallest_user_id() {
+    // Stores written before emails were unique may hold duplicates
+    let mut users: Vec<(User, &str)> = ["first-password", "second-password"]
+        .into_iter()
+        .map(|password| (User::new_with_cost("Test User".to_string(), "shared@example.com".to_string(), password.to_string(), 1, 4).unwrap(), password))
+        .collect();
+    users.sort_by(|(a, _), (b, _)| a.id.cmp(&b.id));
+    let manager = common::load_with_users(users.iter().map(|(user, _)| user.clone()).collect());
+    let [(smallest, smallest_password), (_, other_password)] = &users[..] else { unreachable!() };
+
+    assert_eq!(manager.get_user_by_email("Shared@Example.com").unwrap().unwrap().0.id, smallest.id);
+    assert_eq!(manager.verify_credentials("shared@example.com", smallest_password).unwrap().0.id, smallest.id);
+    let other = manager.verify_credentials("shared@example.com", other_password);
+    assert_eq!(other.unwrap_err(), IamError::Unauthenticated("Invalid credentials".to_string()));
+}
+
+#[tokio::test]
+async fn update_user_changes_only_the_given_fields() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "old@example.com", &["READ", "WRITE"]);
+    common::create_user(&manager, "taken@example.com", &[]);
+
+    manager.update_user(&user.id, None, Some("new@example.com".to_string()), None).unwrap();
+    let response = IamService::get_user(&manager, Request::new(GetUserRequest { user_id: user.id.clone() })).await.unwrap().into_inner();
+    let updated = response.user.unwrap();
+    assert_eq!((updated.name.as_str(), updated.email.as_str()), ("Test User", "new@example.com"));
+    assert_eq!(updated.permissions, common::names(&["READ", "WRITE"]));
+
+    manager.update_user(&user.id, Some("Renamed".to_string()), None, Some("new-password1".to_string())).unwrap();
+    assert!(manager.verify_credentials("new@example.com", "new-password1").is_ok());
+    assert!(manager.verify_credentials("new@example.com", "password123").is_err());
+    assert_eq!(manager.update_user(&user.id, None, Some("TAKEN@example.com".to_string()), None).unwrap_err(), IamError::AlreadyExists("Email already registered".to_string()));
+    assert_eq!(manager.get_user(&user.id).unwrap().name, "Renamed");
+}
+
+#[test]
+fn only_well_formed_emails_are_accepted() {
+    for email in ["user@example.com", "first.last+tag@sub.example.org", " padded@example.com "] {
+        assert!(bitflags_iam::validate_email(email).is_ok(), "{}", email);
+    }
+    for email in ["not-an-email", "@example.com", "user@", "a@b@example.com", "first last@example.com", ""] {
+        assert_eq!(bitflags_iam::validate_email(email).unwrap_err(), IamError::InvalidArgument(format!("Invalid email format: '{}'", email)));
+    }
+
+    let manager = common::manager();
+    let create = manager.create_user("Test User".to_string(), "not-an-email".to_string(), "password123".to_string(), vec![]);
+    assert_eq!(create.unwrap_err(), IamError::InvalidArgument("Invalid email format: 'not-an-email'".to_string()));
+    let user = common::create_user(&manager, "user@example.com", &[]);
+    assert_eq!(manager.update_user(&user.id, None, Some("user@".to_string()), None).unwrap_err(), IamError::InvalidArgument("Invalid email format: 'user@'".to_string()));
+    assert_eq!(manager.get_user(&user.id).unwrap().email, "user@example.com");
+}
+
+#[test]
+fn password_policy_applies_on_create_and_password_update() {
+    let policy = PasswordPolicy { min_length: 10, require_digit: true, require_uppercase: true, require_symbol: true };
+    let manager = common::manager().with_password_policy(policy);
+    let create = |password: &str| manager.create_user("Test User".to_string(), "user@example.com".to_string(), password.to_string(), vec![]);
+
+    assert_eq!(create("weak").unwrap_err(), IamError::InvalidArgument("Password too weak: shorter than 10 characters, no digit, no uppercase letter, no symbol".to_string()));
+    assert_eq!(create("longenough1!").unwrap_err(), IamError::InvalidArgument("Password too weak: no uppercase letter".to_string()));
+    let user = create("Str0ng-enough").unwrap();
+
+    assert!(manager.update_user(&user.id, None, None, Some("Short1!".to_string())).is_err());
+    manager.update_user(&user.id, None, None, Some("An0ther-strong".to_string())).unwrap();
+    assert!(manager.verify_credentials("user@example.com", "An0ther-strong").is_ok());
+
+    // Default policy only asks for 8 characters
+    assert!(PasswordPolicy::default().check("1234567").is_err());
+    assert!(PasswordPolicy::default().check("abcdefgh").is_ok());
+}
+
+#[tokio::test]
+async fn get_user_by_email_matches_the_normalized_email() {
+    let manager = common::manager().with_status_codes(true);
+    let user = common::create_user(&manager, "user@example.com", &["READ"]);
+    let by_email = |email: &str| IamService::get_user_by_email(&manager, Request::new(GetUserByEmailRequest { email: email.to_string() }));
+
+    let found = by_email("  USER@Example.com ").await.unwrap().into_inner();
+    assert!(found.success);
+    let found = found.user.unwrap();
+    assert_eq!((found.id, found.email, found.permissions), (user.id, "user@example.com".to_string(), common::names(&["READ"])));
+
+    assert_eq!(by_email("other@example.com").await.unwrap_err().code(), Code::NotFound);
+}
+
+fn new_user(email: &str, permissions: &[&str]) -> NewUser {
+    NewUser {
+        name: "Test User".to_string(),
+        email: email.to_string(),
+        password: "password123".to_string(),
+        permissions: common::names(permissions),
+    }
+}
+
+fn seed_batch() -> Vec<NewUser> {
+    vec![
+        new_user("first@example.com", &["READ"]),
+        new_user("taken@example.com", &[]),
+        new_user("second@example.com", &["AUDIT"]),
+        new_user("third@example.com", &["WRITE"]),
+    ]
+}
+
+#[test]
+fn batch_create_fails_entries_independently() {
+    let manager = common::manager();
+    common::create_user(&manager, "taken@example.com", &[]);
+
+    let results = manager.batch_create_users(seed_batch(), false).unwrap();
+    assert_eq!(results[0].as_ref().unwrap().email, "first@example.com");
+    assert_eq!(results[1].as_ref().unwrap_err(), &IamError::AlreadyExists("Email already registered".to_string()));
+    assert_eq!(results[2].as_ref().unwrap_err(), &IamError::NotFound("Permission 'AUDIT' not found".to_string()));
+    assert_eq!(results[3].as_ref().unwrap().email, "third@example.com");
+    assert_eq!(manager.all_users().unwrap().len(), 3);
+}
+
+#[test]
+fn atomic_batch_create_persists_nothing_on_failure() {
+    let manager = common::manager();
+    common::create_user(&manager, "taken@example.com", &[]);
+    let rolled_back = IamError::FailedPrecondition("Not created: another entry of the atomic batch failed".to_string());
+
+    let results = manager.batch_create_users(seed_batch(), true).unwrap();
+    assert_eq!(results[0].as_ref().unwrap_err(), &rolled_back);
+    assert_eq!(results[1].as_ref().unwrap_err(), &IamError::AlreadyExists("Email already registered".to_string()));
+    assert_eq!(results[2].as_ref().unwrap_err(), &IamError::NotFound("Permission 'AUDIT' not found".to_string()));
+    assert_eq!(results[3].as_ref().unwrap_err(), &rolled_back);
+
+    // A clash between two entries of the batch rolls back too
+    let results = manager.batch_create_users(vec![new_user("new@example.com", &[]), new_user("NEW@example.com", &[])], true).unwrap();
+    assert_eq!(results[0].as_ref().unwrap_err(), &rolled_back);
+    assert_eq!(results[1].as_ref().unwrap_err(), &IamError::AlreadyExists("Email already registered".to_string()));
+    assert_eq!(manager.all_users().unwrap().len(), 1);
+    assert_eq!(manager.get_audit_log(None, 0).unwrap().len(), 1);
+
+    let created = manager.batch_create_users(vec![new_user("new@example.com", &["READ"])], true).unwrap();
+    assert!(created[0].is_ok());
+    assert_eq!(manager.all_users().unwrap().len(), 2);
+}
+
+#[test]
+fn api_keys_resolve_to_their_user_until_it_is_deleted() {
+    let manager = common::manager();
+    let user = common::create_user(&manager, "admin@example.com", &["READ"]);
+
+    let key = manager.register_api_key(&user.id).unwrap();
+    assert_eq!(manager.resolve_api_key(&key).unwrap(), user.id);
+    assert_eq!(manager.add_api_key(key.clone(), &user.id).unwrap_err(), IamError::AlreadyExists("API key already registered".to_string()));
+    assert_eq!(manager.add_api_key(" ".to_string(), &user.id).unwrap_err(), IamError::InvalidArgument("API key cannot be empty".to_string()));
+    assert_eq!(manager.resolve_api_key("unknown").unwrap_err(), IamError::Unauthenticated("Invalid API key".to_string()));
+
+    manager.delete_user(&user.id).unwrap();
+    assert_eq!(manager.resolve_api_key(&key).unwrap_err(), IamError::Unauthenticated("Invalid API key".to_string()));
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use bitflags_iam::{bits_to_permission_names, find_next_available_bit, bits_to_permission_names_with_order, merge_registries, permission_names_to_bits, permissions_complement, IamError, IamManager, Permission, PermissionRegistry, RegistryConflict, SortOrder};
+
+// Permissions are sequenced in the order given
+fn registry(permissions: &[(&str, u64)]) -> PermissionRegistry {
+    permissions.iter()
+        .enumerate()
+        .map(|(sequence, (name, value))| (name.to_string(), Permission { sequence: sequence as u64, ..Permission::new(name.to_string(), *value) }))
+        .collect()
+}
+
+#[test]
+fn merge_adds_new_permissions_and_keeps_shared_ones() {
+    let merged = merge_registries(&registry(&[("READ", 1), ("WRITE", 2)]), &registry(&[("WRITE", 2), ("ADMIN", 4)])).unwrap();
+
+    assert_eq!(merged.len(), 3);
+    assert_eq!(merged["ADMIN"].value, 4);
+}
+
+#[test]
+fn merge_reports_clashes_with_the_left_registry() {
+    let conflicts = merge_registries(&registry(&[("READ", 1), ("WRITE", 2)]), &registry(&[("WRITE", 4), ("ADMIN", 1)])).unwrap_err();
+
+    assert_eq!(conflicts, vec![
+        RegistryConflict::Name { value: 1, left: "READ".to_string(), right: "ADMIN".to_string() },
+        RegistryConflict::Value { name: "WRITE".to_string(), left: 2, right: 4 },
+    ]);
+    assert_eq!(conflicts[0].detail(), "Value 1 is used by both 'READ' and 'ADMIN'");
+    assert_eq!(conflicts[1].detail(), "Permission 'WRITE' has conflicting values 2 and 4");
+}
+
+#[test]
+fn merge_reports_value_collisions_inside_the_incoming_registry() {
+    let conflicts = merge_registries(&registry(&[("READ", 1)]), &registry(&[("ADMIN", 16), ("AUDIT", 16)])).unwrap_err();
+
+    assert_eq!(conflicts, vec![RegistryConflict::Name { value: 16, left: "ADMIN".to_string(), right: "AUDIT".to_string() }]);
+}
+
+#[test]
+fn sort_orders_decide_the_name_sequence() {
+    let permissions = registry(&[("WRITE", 2), ("ADMIN", 8), ("READ", 1), ("DELETE", 4)]);
+
+    assert_eq!(bits_to_permission_names_with_order(&permissions, 15, SortOrder::Alphabetical, false).unwrap(), ["ADMIN", "DELETE", "READ", "WRITE"]);
+    assert_eq!(bits_to_permission_names_with_order(&permissions, 15, SortOrder::ByValue, false).unwrap(), ["READ", "WRITE", "DELETE", "ADMIN"]);
+    assert_eq!(bits_to_permission_names_with_order(&permissions, 11, SortOrder::Unsorted, false).unwrap(), ["WRITE", "ADMIN", "READ"]);
+    assert_eq!(bits_to_permission_names(&permissions, 6).unwrap(), ["DELETE", "WRITE"]);
+
+    // Names sharing a value come out the same way whatever the map's iteration order
+    let shared = registry(&[("ZETA", 16), ("ALPHA", 16), ("READ", 1)]);
+    assert_eq!(bits_to_permission_names_with_order(&shared, 17, SortOrder::ByValue, false).unwrap(), ["READ", "ALPHA", "ZETA"]);
+}
+
+#[test]
+fn manager_sort_order_applies_to_every_name_list() {
+    let manager = IamManager::new().with_sort_order(SortOrder::ByValue);
+    let user = common::create_user(&manager, "user@example.com", &["WRITE", "READ", "EXECUTE"]);
+
+    assert_eq!(manager.get_user_with_permission_names(&user.id).unwrap().unwrap().1, ["READ", "WRITE", "EXECUTE"]);
+    manager.update_user_permissions(&user.id, common::names(&["DELETE", "READ"])).unwrap();
+    assert_eq!(manager.get_user_with_permission_names(&user.id).unwrap().unwrap().1, ["READ", "DELETE"]);
+    let (_, missing) = manager.check_permissions(&user.id, &common::names(&["WRITE", "EXECUTE", "READ"]), Default::default()).unwrap();
+    assert_eq!(missing, ["WRITE", "EXECUTE"]);
+}
+
+#[test]
+fn unsorted_lists_names_in_registration_order() {
+    let manager = IamManager::new().with_bcrypt_cost(4).unwrap().with_sort_order(SortOrder::Unsorted);
+    common::add_permission(&manager, "ZETA");
+    common::add_permission(&manager, "ALPHA");
+    // A re-registered name takes the freed bit but goes to the end, and renaming keeps the place
+    manager.remove_permission("READ").unwrap();
+    common::add_permission(&manager, "READ");
+    manager.rename_permission("WRITE", "EDIT").unwrap();
+    let user = common::create_user(&manager, "user@example.com", &["READ", "ALPHA", "EDIT", "ZETA"]);
+    assert_eq!(manager.get_user_with_permission_names(&user.id).unwrap().unwrap().1, ["EDIT", "ZETA", "ALPHA", "READ"]);
+
+    // Imported permissions follow the existing ones in their input order
+    let imported = vec![Permission::new("BETA".to_string(), 1 << 30), Permission::new("ALPHA".to_string(), 1 << 31)];
+    let target = IamManager::new().with_bcrypt_cost(4).unwrap().with_sort_order(SortOrder::Unsorted);
+    target.import_permissions(imported).unwrap();
+    let user = common::create_user(&target, "user@example.com", &["ALPHA", "READ", "BETA"]);
+    assert_eq!(target.get_user_with_permission_names(&user.id).unwrap().unwrap().1, ["READ", "BETA", "ALPHA"]);
+}
+
+#[test]
+fn complement_within_the_registered_universe() {
+    let universe = 0b1111;
+    assert_eq!(permissions_complement(0b0101, universe), 0b1010);
+    assert_eq!(permissions_complement(0, universe), universe);
+    assert_eq!(permissions_complement(universe, universe), 0);
+    // Bits outside the universe never appear in the complement
+    assert_eq!(permissions_complement(1 << 40, universe), universe);
+
+    let manager = common::manager();
+    assert_eq!(manager.get_complement(&common::names(&["read", "DELETE"])).unwrap(), ["EXECUTE", "WRITE"]);
+    assert!(manager.get_complement(&common::names(&["READ", "WRITE", "EXECUTE", "DELETE"])).unwrap().is_empty());
+}
+
+#[test]
+fn next_bit_is_the_smallest_free_one() {
+    assert_eq!(find_next_available_bit(&[1, 2, 16, 32]).unwrap(), 4);
+    assert_eq!(find_next_available_bit(&[2, 4]).unwrap(), 1);
+    assert_eq!(find_next_available_bit(&[32, 1, 8, 2]).unwrap(), 4);
+    assert_eq!(find_next_available_bit(&[]).unwrap(), 1);
+}
+
+#[test]
+fn every_unknown_name_is_reported() {
+    let permissions = registry(&[("READ", 1), ("WRITE", 2)]);
+
+    assert_eq!(permission_names_to_bits(&permissions, &common::names(&["READ", "TYPO1", "WRITE", "TYPO2", "TYPO1"])).unwrap_err(), IamError::NotFound("Permissions not found: TYPO1, TYPO2".to_string()));
+    assert_eq!(permission_names_to_bits(&permissions, &common::names(&["TYPO1"])).unwrap_err(), IamError::NotFound("Permission 'TYPO1' not found".to_string()));
+    assert_eq!(permission_names_to_bits(&permissions, &common::names(&["READ", "WRITE"])).unwrap(), 3);
+}
+
+#[test]
+fn next_bit_scans_all_64_positions() {
+    let all: Vec<u64> = (0..64).map(|position| 1u64 << position).collect();
+    assert_eq!(find_next_available_bit(&all).unwrap_err(), IamError::ResourceExhausted("No more permission slots available".to_string()));
+
+    // Full but listed out of order
+    let mut shuffled: Vec<u64> = all.iter().rev().copied().collect();
+    shuffled.swap(3, 40);
+    assert!(find_next_available_bit(&shuffled).is_err());
+
+    // Only the top bit is free
+    let top_free: Vec<u64> = all[..63].to_vec();
+    assert_eq!(find_next_available_bit(&top_free).unwrap(), 1 << 63);
+    // Sparse gap high up, with composite values mixed in
+    let mut sparse: Vec<u64> = all.iter().copied().filter(|&value| value != 1 << 50).collect();
+    sparse.extend([3, u64::MAX]);
+    assert_eq!(find_next_available_bit(&sparse).unwrap(), 1 << 50);
+}
+
+#[test]
+fn collapsing_keeps_the_broadest_composites() {
+    let composites = |permissions: &mut PermissionRegistry, entries: &[(&str, u64)]| {
+        for (name, value) in entries {
+            permissions.insert(name.to_string(), Permission::new_composite(name.to_string(), *value, String::new()));
+        }
+    };
+    let names = |permissions: &PermissionRegistry, bits: u64, collapse: bool| {
+        bits_to_permission_names_with_order(permissions, bits, SortOrder::Alphabetical, collapse).unwrap()
+    };
+
+    for _ in 0..20 {
+        let mut permissions = registry(&[("READ", 1), ("WRITE", 2), ("EXECUTE", 4), ("DELETE", 8)]);
+        composites(&mut permissions, &[("READ_WRITE", 3), ("WRITE_EXECUTE", 6)]);
+
+        assert_eq!(names(&permissions, 3, false), ["READ", "READ_WRITE", "WRITE"]);
+        assert_eq!(names(&permissions, 3, true), ["READ_WRITE"]);
+        assert_eq!(names(&permissions, 3 | 8, true), ["DELETE", "READ_WRITE"]);
+        // Overlapping composites are both kept when each adds bits of its own
+        assert_eq!(names(&permissions, 7, true), ["READ_WRITE", "WRITE_EXECUTE"]);
+
+        composites(&mut permissions, &[("FULL_ACCESS", 7)]);
+        assert_eq!(names(&permissions, 7, true), ["FULL_ACCESS"]);
+        assert_eq!(names(&permissions, 15, true), ["DELETE", "FULL_ACCESS"]);
+    }
+}
+
+#[test]
+fn requested_names_are_trimmed_and_deduplicated() {
+    let permissions = registry(&[("READ", 1), ("WRITE", 2)]);
+
+    assert_eq!(permission_names_to_bits(&permissions, &["read", "READ ", " WRITE", "READ"].map(String::from)).unwrap(), 3);
+    // Unknown names are reported once, in their normalized form
+    assert_eq!(
+        permission_names_to_bits(&permissions, &["typo", " TYPO ", "READ", "Typo"].map(String::from)).unwrap_err(),
+        IamError::NotFound("Permission 'TYPO' not found".to_string())
+    );
+
+    let manager = IamManager::new().with_bcrypt_cost(4).unwrap();
+    let user = manager.create_user("Test User".to_string(), "user@example.com".to_string(), "password123".to_string(), ["READ", " read", "WRITE "].map(String::from).to_vec()).unwrap();
+    assert_eq!(manager.get_user_with_permission_names(&user.id).unwrap().unwrap().1, ["READ", "WRITE"]);
+}